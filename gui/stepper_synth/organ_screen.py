@@ -35,7 +35,8 @@ def draw_speaker(pygame, screen, state: StepperSynthState):
     global LAST_TICK_TIME
     global LAST_THETA
 
-    line_speed = state.gui_params.get(GuiParam.E) * 0.5
+    # E is the 0 to 1 leslie setting, the organ spins at (440 * E) / 60 Hz.
+    line_speed = (440.0 * state.gui_params.get(GuiParam.E)) / 60.0 * 0.5
 
     # claculate time since last update
     ticks = pygame.time.get_ticks()
@@ -1,5 +1,5 @@
 from .controls import Buttons, buttons
-from stepper_synth_backend import StepperSynthState, StepperSynth, StepCmd, Screen
+from stepper_synth_backend import StepperSynthState, StepperSynth, StepCmd, Screen, ClockSource
 from .config import *
 from .utils import *
 from dataclasses import dataclass
@@ -190,8 +190,14 @@ def draw_labels(pygame, screen, fonts, state: StepperSynthState, bottom: float,
                   l, r, "Sequence", state.name, INDEX == 0)
     l, r = l_r[1]
     # draw_tempo(pygame, screen, fonts, top, bottom, l, r, state.tempo)
-    do_draw_label(pygame, screen, fonts, top,
-                  bottom, l, r, "Tempo", f"{state.tempo}", INDEX == 1)
+    if state.clock == ClockSource.External:
+        # following MIDI clock, show what's coming in.
+        tempo = "--" if state.clock_tempo is None else f"{state.clock_tempo:.1f}"
+        do_draw_label(pygame, screen, fonts, top,
+                      bottom, l, r, "Ext Tempo", tempo, INDEX == 1)
+    else:
+        do_draw_label(pygame, screen, fonts, top,
+                      bottom, l, r, "Tempo", f"{state.tempo}", INDEX == 1)
     l, r = l_r[2]
     # draw_step_total(pygame, screen, fonts, top,
     #                 bottom, l, r, len(state.sequence.steps))
@@ -25,6 +25,7 @@ SRC_S = [
     "Macro4",
     "Mod-Whl",
     "Pitch-Whl",
+    "Aftertouch",
 ]
 
 
@@ -0,0 +1,493 @@
+use crate::{
+    effects::{Effect, EffectType},
+    midi_learn::LearnTarget,
+    pygame_coms::{GuiParam, Knob, SynthEngineType, WTSynthParam, WaveTableState},
+    session::Preset,
+    synth_engines::{
+        mpe::MpeZones, synth_common::velocity::Velocity, BendRange, MixerChannel, Synth,
+        SynthEngine, SynthModule, SynthParts, MIDI_CHANNELS,
+    },
+    HashMap, KnobCtrl, MidiControlled,
+};
+use log::*;
+use midi_control::{MidiMessage, MidiNote};
+use rtrb::{Consumer, Producer, PushError, RingBuffer};
+use std::{
+    ops::IndexMut,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+/// how many commands can be waiting for the audio thread at once.
+pub const CMD_QUEUE_SIZE: usize = 1024;
+/// how many sets of replacement engines can be waiting for the audio thread, or waiting to be
+/// dropped after it.
+const PARTS_QUEUE_SIZE: usize = 4;
+
+/// a message sent to the audio thread, which owns the `Synth`.
+#[derive(Debug, Clone)]
+pub enum SynthCmd {
+    /// raw midi from a controller.
+    Midi(MidiMessage),
+    /// play a note on `engine`, or on the current engine if `None`.
+    Play {
+        engine: Option<SynthEngineType>,
+        /// the channel its `Stop` will come on, from 0.
+        channel: u8,
+        note: MidiNote,
+        vel: u8,
+    },
+    /// stop a note on the engine it was played on. held if a pedal is down.
+    Stop {
+        engine: Option<SynthEngineType>,
+        channel: u8,
+        note: MidiNote,
+    },
+    /// a control change for `engine`, or for the current engine if `None`.
+    CC {
+        engine: Option<SynthEngineType>,
+        code: u8,
+        value: u8,
+    },
+    /// release every note on every engine.
+    AllNotesOff,
+    SetEngine(SynthEngineType),
+    /// move `engine` in the stereo field, -1.0 is hard left and 1.0 hard right.
+    Pan {
+        engine: SynthEngineType,
+        pan: f32,
+    },
+    /// set the mixer level of `engine`, from 0.0 to 1.0.
+    Level {
+        engine: SynthEngineType,
+        level: f32,
+    },
+    Mute {
+        engine: SynthEngineType,
+        mute: bool,
+    },
+    /// play MIDI `channel`, from 0, on `engine`, or on the current engine if `None`.
+    MidiRoute {
+        channel: u8,
+        engine: Option<SynthEngineType>,
+    },
+    /// set how `engine` responds to velocity.
+    Velocity {
+        engine: SynthEngineType,
+        velocity: Velocity,
+    },
+    /// set how far the pitch wheel bends `engine`.
+    BendRange {
+        engine: SynthEngineType,
+        range: BendRange,
+    },
+    /// set how many member channels each MPE zone has.
+    MpeZones(MpeZones),
+    SetEffect(EffectType),
+    EffectToggle,
+    TargetEffects(bool),
+    Knob {
+        effect: bool,
+        knob: Knob,
+        value: f32,
+    },
+    Gui {
+        param: GuiParam,
+        value: f32,
+    },
+    WaveTable(WTSynthParam),
+    /// set a parameter bound with MIDI learn, `value` is already scaled to its range.
+    SetTarget {
+        target: LearnTarget,
+        value: f32,
+    },
+    /// point the scope at an engine, or at the output with `None`.
+    ScopeSource(Option<SynthEngineType>),
+    LoadPreset(Box<Preset>),
+    /// swap in the engines and effects `SynthCtrl::recover` left for the audio thread. it's a
+    /// command so they take the settings sent before it and miss none sent after.
+    SwapParts,
+}
+
+impl SynthCmd {
+    /// false for what's played rather than set: notes, bends and pressure. `SynthCtrl`'s copy of
+    /// the synth skips those so it never holds a note.
+    pub fn is_setting(&self) -> bool {
+        match self {
+            Self::Midi(message) => !matches!(
+                message,
+                MidiMessage::NoteOn(_, _)
+                    | MidiMessage::NoteOff(_, _)
+                    | MidiMessage::PitchBend(_, _, _)
+                    | MidiMessage::ChannelPressure(_, _)
+                    | MidiMessage::PolyKeyPressure(_, _)
+            ),
+            Self::Play { .. } | Self::Stop { .. } | Self::AllNotesOff | Self::SwapParts => false,
+            _ => true,
+        }
+    }
+}
+
+/// the sending half of the command queue. it can be cloned and shared between the GUI, MIDI and
+/// sequencer threads; only the audio thread pops from the queue so it never waits on a lock.
+///
+/// it also keeps a copy of the synth that every setting is applied to as it's sent. snapshots and
+/// presets are built from the copy so the audio thread never has to allocate them.
+#[derive(Debug, Clone)]
+pub struct SynthCtrl {
+    queue: Arc<Mutex<Producer<SynthCmd>>>,
+    state: Arc<Mutex<Synth>>,
+    /// set whenever a setting changes something the GUI shows.
+    updated: Arc<AtomicBool>,
+    /// set by the audio thread when the master bus trips.
+    tripped: Arc<AtomicBool>,
+    fresh: Arc<Mutex<Producer<Box<SynthParts>>>>,
+    spent: Arc<Mutex<Consumer<Box<SynthParts>>>>,
+}
+
+/// the audio thread's end of a `SynthCtrl`.
+#[derive(Debug)]
+pub struct SynthFeed {
+    pub cmds: Consumer<SynthCmd>,
+    tripped: Arc<AtomicBool>,
+    fresh: Consumer<Box<SynthParts>>,
+    spent: Producer<Box<SynthParts>>,
+}
+
+impl SynthFeed {
+    /// runs the waiting commands, including swapping in replacement engines. call it before each
+    /// buffer.
+    pub fn apply(&mut self, synth: &mut Synth) {
+        while let Ok(cmd) = self.cmds.pop() {
+            match cmd {
+                SynthCmd::SwapParts => {
+                    if let Ok(mut parts) = self.fresh.pop() {
+                        synth.swap_parts(&mut parts);
+                        // dropped by `SynthCtrl::recover` so the audio thread never frees them.
+                        let _ = self.spent.push(parts);
+                    }
+                }
+                cmd => {
+                    synth.run_cmd(cmd);
+                }
+            }
+        }
+    }
+
+    /// passes a trip of the master bus on to the control side. call it after each buffer.
+    pub fn check(&mut self, synth: &mut Synth) {
+        if synth.master.tripped() {
+            self.tripped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl SynthCtrl {
+    /// `synth` is the one about to be handed to the audio thread, the copy starts out the same.
+    pub fn new(synth: &Synth) -> (Self, SynthFeed) {
+        let (tx, rx) = RingBuffer::new(CMD_QUEUE_SIZE);
+        let (fresh_tx, fresh_rx) = RingBuffer::new(PARTS_QUEUE_SIZE);
+        let (spent_tx, spent_rx) = RingBuffer::new(PARTS_QUEUE_SIZE);
+        let tripped = Arc::new(AtomicBool::new(false));
+
+        (
+            Self {
+                queue: Arc::new(Mutex::new(tx)),
+                state: Arc::new(Mutex::new(synth.detached_clone())),
+                updated: Arc::new(AtomicBool::new(true)),
+                tripped: tripped.clone(),
+                fresh: Arc::new(Mutex::new(fresh_tx)),
+                spent: Arc::new(Mutex::new(spent_rx)),
+            },
+            SynthFeed {
+                cmds: rx,
+                tripped,
+                fresh: fresh_rx,
+                spent: spent_tx,
+            },
+        )
+    }
+
+    pub fn send(&self, cmd: SynthCmd) {
+        // locked first so the copy sees settings in the same order as the audio thread.
+        let mut queue = self.queue.lock().unwrap();
+
+        if cmd.is_setting() && self.state().run_cmd(cmd.clone()) {
+            self.updated.store(true, Ordering::Relaxed);
+        }
+
+        if let Err(PushError::Full(cmd)) = queue.push(cmd) {
+            warn!("synth command queue is full, dropping {cmd:?}");
+        }
+    }
+
+    /// the synth as it's set up right now, without anything that's playing.
+    pub fn state(&self) -> MutexGuard<'_, Synth> {
+        self.state.lock().unwrap()
+    }
+
+    /// the flag `send` sets when the GUI should refresh, shared with everything else that sets it.
+    pub fn updated(&self) -> Arc<AtomicBool> {
+        self.updated.clone()
+    }
+
+    pub fn snapshot(&self) -> SynthSnapshot {
+        self.state().snapshot()
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.state().preset()
+    }
+
+    /// if the master bus tripped, logs it and sends the audio thread engines and effects built from
+    /// the copy, with the current settings and nothing that blew up. also drops the ones they
+    /// replaced. call it regularly from a thread that isn't the audio thread.
+    pub fn recover(&self) {
+        while self.spent.lock().unwrap().pop().is_ok() {}
+
+        if !self.tripped.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        error!("the master bus tripped on runaway output, resetting the engines and effects");
+        let mut queue = self.queue.lock().unwrap();
+        let mut fresh = self.fresh.lock().unwrap();
+
+        if fresh.is_full() || queue.slots() == 0 {
+            warn!("the audio thread is behind, trying again later");
+            self.tripped.store(true, Ordering::Relaxed);
+
+            return;
+        }
+
+        // nothing can be sent while `queue` is locked, so the parts have every setting sent
+        // before `SwapParts` and none after.
+        let _ = fresh.push(Box::new(self.state().parts()));
+        let _ = queue.push(SynthCmd::SwapParts);
+    }
+}
+
+/// a copy of the synth state the GUI cares about.
+#[derive(Debug, Clone)]
+pub struct SynthSnapshot {
+    pub engine: SynthEngineType,
+    pub effect: EffectType,
+    pub effect_on: bool,
+    pub knob_params: HashMap<Knob, f32>,
+    pub gui_params: HashMap<GuiParam, f32>,
+    pub effect_params: HashMap<String, f32>,
+    /// indexed like `Synth::engines`.
+    pub mixer: Box<[MixerChannel]>,
+    /// indexed by MIDI channel from 0.
+    pub midi_routes: [Option<SynthEngineType>; MIDI_CHANNELS],
+    /// indexed like `Synth::engines`, `None` for the engines without velocity settings.
+    pub velocity: Box<[Option<Velocity>]>,
+    /// indexed like `Synth::engines`.
+    pub bend_ranges: Box<[BendRange]>,
+    pub mpe: MpeZones,
+    pub wave_table: Option<WaveTableState>,
+}
+
+impl Synth {
+    /// applies a command from the queue. returns true if the GUI visible state changed.
+    pub fn run_cmd(&mut self, cmd: SynthCmd) -> bool {
+        match cmd {
+            SynthCmd::Midi(message) => {
+                self.midi_input(&message);
+
+                !matches!(
+                    message,
+                    MidiMessage::NoteOn(_, _) | MidiMessage::NoteOff(_, _)
+                )
+            }
+            SynthCmd::Play {
+                engine,
+                channel,
+                note,
+                vel,
+            } => {
+                self.note_on(engine, channel, note, vel);
+                false
+            }
+            SynthCmd::Stop {
+                engine,
+                channel,
+                note,
+            } => {
+                self.note_off(engine, channel, note);
+                false
+            }
+            SynthCmd::CC {
+                engine,
+                code,
+                value,
+            } => {
+                self.control_change(engine, code, value);
+                true
+            }
+            SynthCmd::AllNotesOff => {
+                self.all_notes_off();
+                false
+            }
+            SynthCmd::SetEngine(engine) => self.set_engine(engine),
+            SynthCmd::Pan { engine, pan } => self.set_pan(engine, pan),
+            SynthCmd::Level { engine, level } => self.set_level(engine, level),
+            SynthCmd::Mute { engine, mute } => self.set_mute(engine, mute),
+            SynthCmd::MidiRoute { channel, engine } => self.set_midi_route(channel, engine),
+            SynthCmd::Velocity { engine, velocity } => self.set_velocity(engine, velocity),
+            SynthCmd::BendRange { engine, range } => self.set_bend_range(engine, range),
+            SynthCmd::MpeZones(zones) => self.set_mpe_zones(zones),
+            SynthCmd::SetEffect(effect) => self.set_effect(effect),
+            SynthCmd::EffectToggle => self.effect_toggle(),
+            SynthCmd::TargetEffects(target) => {
+                self.target_effects = target;
+                false
+            }
+            SynthCmd::Knob {
+                effect,
+                knob,
+                value,
+            } => {
+                if effect {
+                    self.get_effect().knob(knob, value);
+                } else {
+                    self.get_engine().knob(knob, value);
+                }
+
+                true
+            }
+            SynthCmd::Gui { param, value } => {
+                self.get_engine().gui_param(param, value);
+                true
+            }
+            SynthCmd::WaveTable(param) => {
+                if let SynthModule::WaveTable(wt) =
+                    self.engines.index_mut(SynthEngineType::WaveTable as usize)
+                {
+                    wt.set_param(param);
+                }
+
+                true
+            }
+            SynthCmd::SetTarget { target, value } => self.set_target(&target, value),
+            SynthCmd::ScopeSource(engine) => {
+                self.scope_source = engine;
+                false
+            }
+            SynthCmd::LoadPreset(preset) => {
+                self.load_preset(&preset);
+                true
+            }
+            // there's nothing to swap in without a `SynthFeed`.
+            SynthCmd::SwapParts => false,
+        }
+    }
+
+    pub(crate) fn engine_for(&mut self, engine: Option<SynthEngineType>) -> &mut SynthModule {
+        match engine {
+            Some(engine) => self.engines.index_mut(engine as usize),
+            None => self.get_engine(),
+        }
+    }
+
+    pub fn snapshot(&self) -> SynthSnapshot {
+        let engine = &self.engines[self.engine_type as usize];
+
+        SynthSnapshot {
+            engine: self.engine_type,
+            effect: self.effect_type,
+            effect_on: self.effect_power,
+            knob_params: engine.get_params(),
+            gui_params: engine.get_gui_params(),
+            effect_params: self.effects[self.effect_type as usize].get_params(),
+            mixer: self.mixer.clone(),
+            midi_routes: self.midi_routes,
+            velocity: self
+                .engines
+                .iter()
+                .map(|engine| engine.velocity())
+                .collect(),
+            bend_ranges: self.bend_ranges.clone(),
+            mpe: self.mpe.zones,
+            wave_table: self.engines.iter().find_map(|engine| {
+                if let SynthModule::WaveTable(wt) = engine {
+                    Some(WaveTableState::from(&*wt))
+                } else {
+                    None
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::set_sample_rate;
+
+    #[test]
+    fn the_copy_takes_settings_but_not_notes() {
+        set_sample_rate(48_000);
+        let synth = Synth::new();
+        let (ctrl, feed) = SynthCtrl::new(&synth);
+
+        ctrl.send(SynthCmd::SetEngine(SynthEngineType::B3Organ));
+        ctrl.send(SynthCmd::Knob {
+            effect: false,
+            knob: Knob::Three,
+            value: 0.25,
+        });
+        ctrl.send(SynthCmd::Play {
+            engine: None,
+            channel: 0,
+            note: 60,
+            vel: 100,
+        });
+
+        let engine = SynthEngineType::B3Organ as usize;
+        let state = ctrl.state();
+        assert_eq!(state.engines[engine].get_params()[&Knob::Three], 0.25);
+        assert!(!state.engines[engine].is_active());
+        drop(state);
+
+        // the audio thread still gets every command.
+        assert_eq!(feed.cmds.slots(), 3);
+    }
+
+    #[test]
+    fn a_trip_swaps_in_fresh_engines_that_keep_every_setting() {
+        set_sample_rate(48_000);
+        let mut synth = Synth::new();
+        let (ctrl, mut feed) = SynthCtrl::new(&synth);
+        let engine = synth.engine_type as usize;
+
+        ctrl.send(SynthCmd::Play {
+            engine: None,
+            channel: 0,
+            note: 60,
+            vel: 100,
+        });
+        feed.apply(&mut synth);
+        assert!(synth.engines[engine].is_active());
+
+        // as if the audio thread had seen the master bus trip.
+        ctrl.tripped.store(true, Ordering::Relaxed);
+        ctrl.recover();
+        ctrl.send(SynthCmd::Knob {
+            effect: false,
+            knob: Knob::Three,
+            value: 0.25,
+        });
+        feed.apply(&mut synth);
+
+        assert!(!synth.engines[engine].is_active());
+        assert_eq!(synth.engines[engine].get_params()[&Knob::Three], 0.25);
+
+        // the old engines go back to be dropped off the audio thread.
+        assert_eq!(ctrl.spent.lock().unwrap().slots(), 1);
+        ctrl.recover();
+        assert_eq!(ctrl.spent.lock().unwrap().slots(), 0);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    effects::EffectType,
+    midi_clock::ClockSource,
+    midi_learn::MidiLearn,
+    patch_bank::PatchBank,
+    pygame_coms::SynthEngineType,
+    sequencer::SequencerIntake,
+    synth_engines::{mpe::MpeZones, Synth},
+    DEFAULT_BUFFER_SIZE, DEFAULT_SAMPLE_RATE,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// env var pointing at the config file, checked before the default locations.
+pub const CONFIG_ENV_VAR: &str = "STEPPER_SYNTH_CONFIG";
+
+/// startup settings, read from a TOML file. every field is optional in the file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub engine: SynthEngineType,
+    pub effect: EffectType,
+    pub bpm: u16,
+    pub sample_rate: u32,
+    /// frames per audio callback.
+    pub buffer_size: usize,
+    /// where logs are written, on top of stderr.
+    pub log_file: Option<PathBuf>,
+    pub transport: TransportCCs,
+    /// the CCs for knobs one through eight.
+    pub knob_ccs: [u8; 8],
+    pub midi_ports: PortFilter,
+    /// whether the sequencer keeps its own time or follows incoming MIDI clock.
+    pub clock: ClockSource,
+    /// a MIDI output to send clock and transport to while keeping time, matched like `midi_ports`.
+    pub clock_out: Option<String>,
+    /// MIDI channels that always play one engine. the rest play the current engine.
+    pub midi_routes: Vec<MidiRoute>,
+    /// member channels for each MPE zone, both 0 leaves MPE off until a controller turns it on.
+    pub mpe: MpeZones,
+    /// where CCs bound with MIDI learn are kept, they're saved every time one is learned.
+    pub midi_learn_file: Option<PathBuf>,
+    /// where patches for Program Change are kept, they're saved every time one is stored.
+    pub patch_bank_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            engine: SynthEngineType::B3Organ,
+            effect: EffectType::Reverb,
+            bpm: 120,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            // only the handheld logs to a file by default.
+            log_file: if cfg!(target_arch = "aarch64") {
+                Some("stepper-synth.log".into())
+            } else {
+                None
+            },
+            transport: TransportCCs::default(),
+            knob_ccs: [70, 71, 72, 73, 74, 75, 76, 77],
+            midi_ports: PortFilter::default(),
+            clock: ClockSource::Internal,
+            clock_out: None,
+            midi_routes: Vec::new(),
+            mpe: MpeZones::default(),
+            midi_learn_file: Some("midi-learn.toml".into()),
+            patch_bank_file: Some("patches.toml".into()),
+        }
+    }
+}
+
+impl Config {
+    /// where to look for a config: `$STEPPER_SYNTH_CONFIG`, then `stepper-synth.toml` in the working
+    /// directory, then `~/.config/stepper-synth/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Ok(path) = env::var(CONFIG_ENV_VAR) {
+            return Some(path.into());
+        }
+
+        let local = PathBuf::from("stepper-synth.toml");
+
+        if local.exists() {
+            return Some(local);
+        }
+
+        let home = env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| env::var("HOME").map(|home| Path::new(&home).join(".config")))
+            .ok()?
+            .join("stepper-synth")
+            .join("config.toml");
+
+        home.exists().then_some(home)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// loads `path`, or the file at `default_path`. no file at all gives the defaults.
+    pub fn find(path: Option<PathBuf>) -> Result<Self> {
+        match path.or_else(Self::default_path) {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// the CCs that drive the step sequencer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportCCs {
+    pub prev_step: u8,
+    pub next_step: u8,
+    pub stop: u8,
+    pub play: u8,
+    pub record: u8,
+}
+
+impl Default for TransportCCs {
+    fn default() -> Self {
+        Self {
+            prev_step: 115,
+            next_step: 116,
+            stop: 117,
+            play: 118,
+            record: 119,
+        }
+    }
+}
+
+/// plays MIDI `channel`, from 0, on `engine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiRoute {
+    pub channel: u8,
+    pub engine: SynthEngineType,
+}
+
+/// which MIDI inputs get connected. names match if they contain an entry, ignoring case.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortFilter {
+    /// if not empty, only ports matching one of these are connected.
+    pub allow: Vec<String>,
+    /// ports matching any of these are never connected, even if allowed.
+    pub deny: Vec<String>,
+}
+
+impl Default for PortFilter {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            // ALSA's loopback port echoes everything sent to it.
+            deny: vec!["Midi Through".into()],
+        }
+    }
+}
+
+impl PortFilter {
+    pub fn allows(&self, port_name: &str) -> bool {
+        let port_name = port_name.to_lowercase();
+        let matches = |pattern: &String| port_name.contains(&pattern.to_lowercase());
+
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+impl Synth {
+    /// applies the startup settings from `config`.
+    pub fn configure(&mut self, config: &Config) {
+        self.set_engine(config.engine);
+        self.set_effect(config.effect);
+        self.knob_ccs = config.knob_ccs;
+
+        for route in config.midi_routes.iter() {
+            self.set_midi_route(route.channel, Some(route.engine));
+        }
+
+        self.set_mpe_zones(config.mpe);
+    }
+}
+
+impl SequencerIntake {
+    /// applies the startup settings from `config`.
+    pub fn configure(&mut self, config: &Config) {
+        self.bpm = config.bpm;
+        self.transport = config.transport;
+        self.clock.source = config.clock;
+        self.clock.out = config.clock_out.clone();
+        self.learn = MidiLearn::load(config.midi_learn_file.clone());
+        self.patches = PatchBank::load(config.patch_bank_file.clone());
+    }
+}
@@ -1,8 +1,8 @@
 use super::{Effect, EffectParam};
-use crate::{synth_engines::LfoInput, HashMap, KnobCtrl, SampleGen, SAMPLE_RATE};
+use crate::{sample_rate, synth_engines::LfoInput, HashMap, KnobCtrl, SampleGen, StereoFrame};
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 use strum::{EnumIter, IntoEnumIterator};
 
 #[cfg_attr(
@@ -38,6 +38,18 @@ impl TryFrom<f32> for ChorusParam {
     }
 }
 
+impl FromStr for ChorusParam {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vol." | "vol" | "volume" => Ok(Self::Volume),
+            "speed" => Ok(Self::Speed),
+            _ => Err(format!("unknown chorus param {s}")),
+        }
+    }
+}
+
 #[cfg(feature = "pyo3")]
 #[pymethods]
 impl ChorusParam {
@@ -48,36 +60,46 @@ impl ChorusParam {
 
 impl EffectParam for ChorusParam {}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Chorus {
+    pub sample_rate: f32,
     pub size: usize,
-    pub buff: [f32; SAMPLE_RATE as usize],
+    /// one second of audio.
+    pub buff: Vec<f32>,
     pub insert_i: usize,
     pub get_i: usize,
     pub step: usize,
+    /// how far behind the left read head the right one sits, in samples.
+    pub spread: usize,
     pub volume: f32,
     pub speed: f32,
-    pub input: f32,
+    pub input: StereoFrame,
     lfo_input: LfoInput,
 }
 
 impl Chorus {
     pub fn new() -> Self {
+        let sample_rate = sample_rate();
+
         Self {
-            size: SAMPLE_RATE as usize,
-            buff: [0.0; SAMPLE_RATE as usize],
+            sample_rate: sample_rate as f32,
+            size: sample_rate as usize,
+            buff: vec![0.0; sample_rate as usize],
             insert_i: 0,
             get_i: 0,
-            step: (SAMPLE_RATE as f32 * (0.25 * 0.5)) as usize,
+            step: (sample_rate as f32 * (0.25 * 0.5)) as usize,
+            spread: (sample_rate as f32 * 0.0125) as usize,
             volume: 0.75,
             speed: 0.25,
-            input: 0.0,
+            input: StereoFrame::default(),
             lfo_input: LfoInput::default(),
         }
     }
 
-    pub fn get_sample(&mut self) -> f32 {
-        let chorus = self.buff[self.get_i] + self.input;
+    pub fn get_frame(&mut self) -> StereoFrame {
+        let left = self.buff[self.get_i] + self.input.left;
+        let right =
+            self.buff[(self.get_i + self.size - self.spread) % self.size] + self.input.right;
         // self.buff[self.i ] = echo;
         // self.buff[(self.i + self.step) % self.size] = chorus * self.volume;
         // self.buff[self.i] = 0.0;
@@ -86,7 +108,7 @@ impl Chorus {
         // if echo == input_sample && input_sample != 0.0 {
         //     error!("[error] {}", self.i);
         // }
-        chorus.tanh()
+        StereoFrame { left, right }.map(f32::tanh)
     }
 
     /// sets speed, takes speed in seconds
@@ -94,7 +116,7 @@ impl Chorus {
         // info!("speed: {}", speed);
         self.speed = speed;
         // self.step = (SAMPLE_RATE as f32 * (speed * 0.05)) as usize;
-        self.step = (SAMPLE_RATE as f32 * (speed * 0.5)) as usize;
+        self.step = (self.sample_rate * (speed * 0.5)) as usize;
         // info!("step:  {}", self.step);
     }
 
@@ -105,7 +127,11 @@ impl Chorus {
 
 impl SampleGen for Chorus {
     fn get_sample(&mut self) -> f32 {
-        self.get_sample()
+        self.get_frame().to_mono()
+    }
+
+    fn get_frame(&mut self) -> StereoFrame {
+        self.get_frame()
     }
 }
 
@@ -116,9 +142,9 @@ impl KnobCtrl for Chorus {
 }
 
 impl Effect for Chorus {
-    fn take_input(&mut self, value: f32) {
+    fn take_input(&mut self, value: StereoFrame) {
         self.input = value * self.volume;
-        self.buff[self.insert_i] = self.input;
+        self.buff[self.insert_i] = self.input.to_mono();
         self.insert_i += 1;
         self.insert_i %= self.size;
     }
@@ -130,14 +156,22 @@ impl Effect for Chorus {
     }
 
     fn get_params(&self) -> crate::HashMap<String, f32> {
-        let map = HashMap::default();
+        let mut map = HashMap::default();
 
-        // TODO: Write this
+        map.insert(format!("{}", ChorusParam::Volume), self.volume);
+        map.insert(format!("{}", ChorusParam::Speed), self.speed);
 
         map
     }
 
-    fn set_param(&mut self, _param: &str, _to: f32) {
-        // TODO: Write this
+    fn set_param(&mut self, param: &str, to: f32) {
+        let Ok(param) = ChorusParam::from_str(param) else {
+            return;
+        };
+
+        match param {
+            ChorusParam::Volume => self.set_volume(to),
+            ChorusParam::Speed => self.set_speed(to),
+        }
     }
 }
@@ -1,12 +1,15 @@
-use crate::{HashMap, KnobCtrl, SampleGen};
+use crate::{HashMap, KnobCtrl, SampleGen, StereoFrame};
 use chorus::Chorus;
 use enum_dispatch::enum_dispatch;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use reverb::Reverb;
 use serde::{Deserialize, Serialize};
-use std::fmt::{Debug, Display};
-use strum::EnumIter;
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+use strum::{EnumIter, IntoEnumIterator};
 
 pub mod chorus;
 pub mod reverb;
@@ -44,6 +47,16 @@ impl Display for EffectType {
     }
 }
 
+impl FromStr for EffectType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::iter()
+            .find(|effect| s.eq_ignore_ascii_case(&format!("{effect}")))
+            .ok_or_else(|| format!("unknown effect {s}"))
+    }
+}
+
 #[cfg(feature = "pyo3")]
 #[cfg_attr(feature = "pyo3", pymethods)]
 impl EffectType {
@@ -58,7 +71,14 @@ pub trait EffectParam: Debug + Clone + Display + TryFrom<f32> {}
 pub trait Effect: Debug + SampleGen + Send + KnobCtrl {
     // type Param: EffectParam;
 
-    fn take_input(&mut self, value: f32);
+    fn take_input(&mut self, value: StereoFrame);
+    /// runs the effect over a block of frames in place.
+    fn process_block(&mut self, buffer: &mut [StereoFrame]) {
+        for frame in buffer.iter_mut() {
+            self.take_input(*frame);
+            *frame = self.get_frame();
+        }
+    }
     fn get_param_list(&self) -> Vec<String>;
     fn get_params(&self) -> HashMap<String, f32>;
     fn set_param(&mut self, param: &str, to: f32);
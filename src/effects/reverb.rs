@@ -2,7 +2,7 @@ use super::{Effect, EffectParam};
 use crate::{
     pygame_coms::Knob,
     synth_engines::{synth_common::lfo::default_lfo_param_tweek, LfoInput, Param},
-    HashMap, KnobCtrl, SampleGen,
+    HashMap, KnobCtrl, SampleGen, StereoFrame,
 };
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
@@ -73,12 +73,17 @@ impl FromStr for ReverbParam {
     }
 }
 
+/// the right channel decays a touch faster than the left so the two tails drift apart, even
+/// when fed the same mono signal.
+const RIGHT_DECAY_SKEW: f32 = 0.93;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Reverb {
-    pub effect: reverb::Reverb,
+    /// one reverb per channel, `[left, right]`.
+    pub effect: [reverb::Reverb; 2],
     pub gain: f32,
     pub decay: f32,
-    in_sample: f32,
+    in_sample: StereoFrame,
     damping: f32,
     cutoff: f32,
     // lfo_sample: f32,
@@ -89,10 +94,10 @@ pub struct Reverb {
 impl Reverb {
     pub fn new() -> Self {
         Self {
-            effect: reverb::Reverb::new(),
+            effect: [reverb::Reverb::new(), reverb::Reverb::new()],
             gain: 0.75,
             decay: 0.5,
-            in_sample: 0.0,
+            in_sample: StereoFrame::default(),
             damping: 0.0,
             cutoff: 1.0,
             // lfo_sample: 0.0,
@@ -101,7 +106,7 @@ impl Reverb {
         }
     }
 
-    pub fn get_sample(&mut self, in_sample: f32) -> f32 {
+    pub fn process(&mut self, in_sample: StereoFrame) -> StereoFrame {
         let gain = if self
             .lfo_input
             .target
@@ -114,7 +119,15 @@ impl Reverb {
 
         self.lfo_step();
 
-        self.effect.calc_sample(in_sample, gain)
+        // cross feed a little of each side into the other so hard panned sources still fill the
+        // whole room.
+        let left = in_sample.left * 0.8 + in_sample.right * 0.2;
+        let right = in_sample.right * 0.8 + in_sample.left * 0.2;
+
+        StereoFrame {
+            left: self.effect[0].calc_sample(left, gain),
+            right: self.effect[1].calc_sample(right, gain),
+        }
     }
 
     /// apply lfo to controls
@@ -129,25 +142,34 @@ impl Reverb {
     pub fn set_decay(&mut self, decay: f32) {
         self.decay = decay;
 
-        self.effect = self.effect.decay(decay).clone();
+        self.effect[0].decay(decay);
+        self.effect[1].decay(decay * RIGHT_DECAY_SKEW);
     }
 
     pub fn set_damping(&mut self, value: f32) {
         self.damping = value;
 
-        self.effect = self.effect.damping(value).clone();
+        for effect in self.effect.iter_mut() {
+            effect.damping(value);
+        }
     }
 
     pub fn set_cutoff(&mut self, value: f32) {
         self.cutoff = value;
 
-        self.effect = self.effect.bandwidth(value).clone();
+        for effect in self.effect.iter_mut() {
+            effect.bandwidth(value);
+        }
     }
 }
 
 impl SampleGen for Reverb {
     fn get_sample(&mut self) -> f32 {
-        self.get_sample(self.in_sample)
+        self.process(self.in_sample).to_mono()
+    }
+
+    fn get_frame(&mut self) -> StereoFrame {
+        self.process(self.in_sample)
     }
 }
 
@@ -188,10 +210,16 @@ impl KnobCtrl for Reverb {
 impl Effect for Reverb {
     // type Param = ReverbParam;
 
-    fn take_input(&mut self, value: f32) {
+    fn take_input(&mut self, value: StereoFrame) {
         self.in_sample = value;
     }
 
+    fn process_block(&mut self, buffer: &mut [StereoFrame]) {
+        for frame in buffer.iter_mut() {
+            *frame = self.process(*frame);
+        }
+    }
+
     fn get_param_list(&self) -> Vec<String> {
         ReverbParam::iter()
             .map(|param| format!("{param}"))
@@ -1,53 +1,200 @@
 #![feature(let_chains)]
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use commands::{SynthCmd, SynthFeed};
+#[cfg(feature = "pyo3")]
 use effects::reverb::ReverbParam;
+#[cfg(feature = "pyo3")]
 use effects::EffectType;
 use effects::EffectsModule;
 use enum_dispatch::enum_dispatch;
 use fern::colors::{Color, ColoredLevelConfig};
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
+use load_monitor::{CallbackLoad, LoadMonitor};
 use log::*;
+#[cfg(feature = "pyo3")]
+use meter::Level;
+use meter::Levels;
+use midi_clock::ClockMessage;
+#[cfg(feature = "pyo3")]
+use midi_clock::ClockSource;
+#[cfg(feature = "pyo3")]
+use midi_learn::{LearnTarget, MidiBinding};
 use midi_control::MidiMessage;
+#[cfg(feature = "pyo3")]
+use midi_ports::MidiPort;
+use midi_ports::{MidiPorts, MIDI_CLIENT_NAME};
 use midir::MidiInput;
-use midir::{Ignore, PortInfoError};
-use pygame_coms::Screen;
+use midir::{Ignore, MidiInputConnection};
+#[cfg(feature = "pyo3")]
+use patch_bank::PatchSlot;
+use pygame_coms::{GuiParam, Knob};
 #[cfg(feature = "pyo3")]
-use pygame_coms::StepperSynth;
-use pygame_coms::StepperSynthState;
-use pygame_coms::{GuiParam, Knob, SynthEngineType};
+use pygame_coms::{Screen, StepperSynth, StepperSynthState, SynthEngineType};
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
-use sequencer::Sequence;
+use scope::ScopeTx;
 use sequencer::SequencerIntake;
-use sequencer::Step;
-use sequencer::StepCmd;
-use std::sync::atomic::AtomicBool;
+#[cfg(feature = "pyo3")]
+use sequencer::{MidiOutTarget, Sequence, Step, StepCmd};
+use std::ops::{Add, AddAssign, Mul};
+use std::path::PathBuf;
 use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, AtomicU32};
 use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+#[cfg(feature = "pyo3")]
+use synth_engines::mpe::MpeZones;
+#[cfg(feature = "pyo3")]
 use synth_engines::synth::OscType;
+#[cfg(feature = "pyo3")]
+use synth_engines::synth_common::velocity::{Velocity, VelocityCurve};
+#[cfg(feature = "pyo3")]
+use synth_engines::BendRange;
 use synth_engines::LfoInput;
+#[cfg(feature = "pyo3")]
+use synth_engines::MixerChannel;
 use synth_engines::Param;
 use synth_engines::Synth;
 use synth_engines::SynthModule;
+use sysex::{SysExReceiver, SYSEX_START};
+use tinyaudio::prelude::*;
 
 pub type HashMap<Key, Val> = FxHashMap<Key, Val>;
 pub type HashSet<T> = FxHashSet<T>;
 
-pub const SAMPLE_RATE: u32 = 48_000;
+/// the sample rate used unless another is picked at startup.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+/// frames per channel handed to the audio callback unless another size is picked at startup.
+pub const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+static SAMPLE_RATE: AtomicU32 = AtomicU32::new(DEFAULT_SAMPLE_RATE);
 
+/// the sample rate the audio device runs at. DSP structs read it when they are built.
+pub fn sample_rate() -> u32 {
+    SAMPLE_RATE.load(Ordering::Relaxed)
+}
+
+/// picks the sample rate. has to be called before the `Synth` is built, anything made earlier keeps
+/// the old rate.
+pub fn set_sample_rate(rate: u32) {
+    SAMPLE_RATE.store(rate, Ordering::Relaxed);
+}
+
+pub mod commands;
+pub mod config;
 pub mod effects;
+pub mod load_monitor;
+pub mod master_bus;
+pub mod meter;
+pub mod midi_clock;
+pub mod midi_learn;
+pub mod midi_ports;
+pub mod patch_bank;
 pub mod pygame_coms;
+pub mod render;
+pub mod scope;
 pub mod sequencer;
+pub mod session;
 pub mod synth_engines;
+pub mod sysex;
 
 pub trait MidiControlled {
     fn midi_input(&mut self, message: &MidiMessage);
 }
 
+/// one sample of stereo audio.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct StereoFrame {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl StereoFrame {
+    /// the same sample in both ears.
+    pub fn mono(sample: f32) -> Self {
+        Self {
+            left: sample,
+            right: sample,
+        }
+    }
+
+    /// places a mono sample in the stereo field. `pan` goes from -1.0 (hard left) to 1.0 (hard
+    /// right), centered samples are left at unity gain.
+    pub fn panned(sample: f32, pan: f32) -> Self {
+        let pan = pan.clamp(-1.0, 1.0);
+
+        Self {
+            left: sample * (1.0 - pan).min(1.0),
+            right: sample * (1.0 + pan).min(1.0),
+        }
+    }
+
+    /// mixes both channels down to one.
+    pub fn to_mono(self) -> f32 {
+        (self.left + self.right) * 0.5
+    }
+
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Self {
+        Self {
+            left: f(self.left),
+            right: f(self.right),
+        }
+    }
+}
+
+impl Add for StereoFrame {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            left: self.left + rhs.left,
+            right: self.right + rhs.right,
+        }
+    }
+}
+
+impl AddAssign for StereoFrame {
+    fn add_assign(&mut self, rhs: Self) {
+        self.left += rhs.left;
+        self.right += rhs.right;
+    }
+}
+
+impl Mul<f32> for StereoFrame {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self {
+            left: self.left * rhs,
+            right: self.right * rhs,
+        }
+    }
+}
+
 #[enum_dispatch(EffectsModule, SynthModule)]
 pub trait SampleGen {
     fn get_sample(&mut self) -> f32;
+
+    /// fills `buffer` with the next `buffer.len()` samples.
+    fn get_block(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.get_sample();
+        }
+    }
+
+    /// the next sample as a stereo frame. mono sources put the same sample in both channels.
+    fn get_frame(&mut self) -> StereoFrame {
+        StereoFrame::mono(self.get_sample())
+    }
+
+    /// fills `buffer` with the next `buffer.len()` stereo frames.
+    fn get_frames(&mut self, buffer: &mut [StereoFrame]) {
+        for frame in buffer.iter_mut() {
+            *frame = self.get_frame();
+        }
+    }
 }
 
 #[allow(unused_variables)]
@@ -105,6 +252,32 @@ pub trait KnobCtrl {
         false
     }
 
+    fn knob(&mut self, knob: Knob, value: f32) -> bool {
+        match knob {
+            Knob::One => self.knob_1(value),
+            Knob::Two => self.knob_2(value),
+            Knob::Three => self.knob_3(value),
+            Knob::Four => self.knob_4(value),
+            Knob::Five => self.knob_5(value),
+            Knob::Six => self.knob_6(value),
+            Knob::Seven => self.knob_7(value),
+            Knob::Eight => self.knob_8(value),
+        }
+    }
+
+    fn gui_param(&mut self, param: GuiParam, value: f32) -> bool {
+        match param {
+            GuiParam::A => self.gui_param_1(value),
+            GuiParam::B => self.gui_param_2(value),
+            GuiParam::C => self.gui_param_3(value),
+            GuiParam::D => self.gui_param_4(value),
+            GuiParam::E => self.gui_param_5(value),
+            GuiParam::F => self.gui_param_6(value),
+            GuiParam::G => self.gui_param_7(value),
+            GuiParam::H => self.gui_param_8(value),
+        }
+    }
+
     fn get_lfo_input(&mut self) -> &mut LfoInput;
 
     fn lfo_connect(&mut self, param: Param) {
@@ -133,70 +306,249 @@ impl Iterator for Player {
     }
 }
 
-#[cfg(feature = "pyo3")]
-fn run_midi(
+/// everything the audio thread publishes. it never waits on any of these, if something is locked
+/// it tries again next buffer.
+pub struct AudioTaps {
+    /// updated every buffer.
+    pub levels: Arc<Mutex<Levels>>,
+    /// updated every buffer.
+    pub load: Arc<Mutex<CallbackLoad>>,
+    /// gets the output, or the engine picked by `Synth::scope_source`.
+    pub scope: Option<ScopeTx>,
+}
+
+/// starts audio playback at `sample_rate()`, asking for `buffer_size` frames per callback. the
+/// audio thread owns `synth` outright, it applies any queued commands before rendering each buffer
+/// and publishes to `taps` after.
+pub fn run_audio(
+    mut synth: Synth,
+    mut feed: SynthFeed,
+    mut taps: AudioTaps,
+    buffer_size: usize,
+) -> Result<OutputDevice> {
+    let params = OutputDeviceParameters {
+        channels_count: 2,
+        sample_rate: sample_rate() as usize,
+        channel_sample_count: buffer_size,
+    };
+
+    info!(
+        "opening audio device at {} Hz with {} frame buffers ({:.1} ms)",
+        params.sample_rate,
+        params.channel_sample_count,
+        params.channel_sample_count as f32 / params.sample_rate as f32 * 1000.0
+    );
+
+    let mut frames = vec![StereoFrame::default(); params.channel_sample_count];
+    // the meters are read into this when the GUI holds `levels`, so they still reset every buffer.
+    let mut spare_levels = taps.levels.lock().unwrap().clone();
+    let mut monitor = LoadMonitor::new();
+
+    run_output_device(params, move |data| {
+        let start = Instant::now();
+
+        feed.apply(&mut synth);
+
+        let n_frames = data.len() / params.channels_count;
+
+        if frames.len() < n_frames {
+            frames.resize(n_frames, StereoFrame::default());
+        }
+
+        synth.get_frames(&mut frames[..n_frames]);
+        feed.check(&mut synth);
+
+        for (out, frame) in data.chunks_mut(params.channels_count).zip(frames.iter()) {
+            out[0] = frame.left;
+            out[1] = frame.right;
+        }
+
+        match taps.levels.try_lock() {
+            Ok(mut levels) => synth.meters.finish(n_frames, &mut levels),
+            Err(_) => synth.meters.finish(n_frames, &mut spare_levels),
+        }
+
+        if let Some(scope) = taps.scope.as_mut() {
+            match synth.scope_samples() {
+                Some(samples) => scope.extend(samples.iter().copied()),
+                None => scope.extend(frames[..n_frames].iter().map(|frame| frame.to_mono())),
+            }
+        }
+
+        let callback_load = monitor.record(start, n_frames, &synth);
+
+        if let Ok(mut load) = taps.load.try_lock() {
+            *load = callback_load;
+        }
+    })
+    .map_err(|e| anyhow!("{e}"))
+}
+
+/// how long the audio keeps running on shutdown after every note is let go, so the release tails
+/// fade out instead of being cut off.
+const RELEASE_TAIL: Duration = Duration::from_millis(250);
+
+/// stops the transport and lets go of every note, then waits while the audio thread plays the note
+/// offs and their tails. call it before setting `exit`, which stops the threads and the device.
+pub fn release_all(seq: &Mutex<SequencerIntake>, buffer_size: usize) {
+    {
+        let seq = seq.lock().unwrap();
+        seq.state.playing.store(false, Ordering::Relaxed);
+        seq.synth.send(SynthCmd::AllNotesOff);
+    }
+
+    // at least two buffers, so the one the command lands in is played out.
+    let buffer = Duration::from_secs_f32(buffer_size as f32 / sample_rate() as f32);
+    sleep(RELEASE_TAIL.max(buffer * 2));
+}
+
+/// how long `run_midi` waits before looking for new ports again, unless the GUI changes a port.
+const MIDI_RESCAN: Duration = Duration::from_secs(1);
+/// how often `run_midi` checks for `exit`, port changes from the GUI and a tripped master bus
+/// while it waits.
+const MIDI_POLL: Duration = Duration::from_millis(20);
+
+/// connects every MIDI input `ports` has enabled to `synth`, rescanning for ports that come and go
+/// until `exit` is set, then closes every connection. the ports it finds are published to `ports`.
+/// it also recovers the synth when the master bus trips, see `SynthCtrl::recover`.
+pub fn run_midi(
     synth: Arc<Mutex<SequencerIntake>>,
-    updated: Arc<Mutex<bool>>,
+    updated: Arc<AtomicBool>,
     exit: Arc<AtomicBool>,
+    ports: Arc<Mutex<MidiPorts>>,
     // effect_midi: Arc<AtomicBool>,
 ) -> Result<()> {
-    let mut registered_ports = HashMap::default();
+    let scanner = MidiInput::new(MIDI_CLIENT_NAME)?;
+    let mut connections: HashMap<String, MidiInputConnection<()>> = HashMap::default();
+    // ports that failed to connect, so the failure is only logged once.
+    let mut failed: HashSet<String> = HashSet::default();
+    let ctrl = synth.lock().unwrap().synth.clone();
 
     while !exit.load(Ordering::Relaxed) {
-        let mut midi_in = MidiInput::new("midir reading input")?;
-        midi_in.ignore(Ignore::None);
-
-        // Get an input port (read from console if multiple are available)
-        let in_ports = midi_in.ports();
-        let port_names: Vec<std::result::Result<String, PortInfoError>> = in_ports
+        let in_ports = scanner.ports();
+        let names: Vec<Option<String>> = in_ports
             .iter()
-            .map(|port| midi_in.port_name(port))
+            .map(|port| scanner.port_name(port).ok())
             .collect();
-        registered_ports.retain(|k: &String, _| port_names.contains(&Ok(k.clone())));
+        let wanted: Vec<bool> = {
+            let ports = ports.lock().unwrap();
+
+            names
+                .iter()
+                .map(|name| name.as_ref().is_some_and(|name| ports.enabled(name)))
+                .collect()
+        };
+        let n_connections = connections.len();
+
+        // unplugged, or switched off from the GUI.
+        connections.retain(|name, _| {
+            names
+                .iter()
+                .zip(wanted.iter())
+                .any(|(port, wanted)| *wanted && port.as_ref() == Some(name))
+        });
+        failed.retain(|name| names.contains(&Some(name.clone())));
+
+        if connections.len() < n_connections {
+            info!(
+                "disconnected {} MIDI port(s)",
+                n_connections - connections.len()
+            );
+        }
 
-        for in_port in in_ports.iter() {
-            let Ok(port_name) = midi_in.port_name(in_port) else {
+        for ((in_port, name), wanted) in in_ports.iter().zip(names.iter()).zip(wanted) {
+            let Some(name) = name else {
                 continue;
             };
 
-            if registered_ports.contains_key(&port_name) {
+            if !wanted || connections.contains_key(name) {
                 continue;
             }
 
-            info!("port {port_name}");
-            let mut midi_in = MidiInput::new("midir reading input")?;
+            let mut midi_in = MidiInput::new(MIDI_CLIENT_NAME)?;
             midi_in.ignore(Ignore::None);
             let synth = synth.clone();
             // let tx = tx.clone();
             let updated = updated.clone();
             // let effect = effect_midi.clone();
-
-            registered_ports.insert(
-                port_name,
-                midi_in.connect(
-                    in_port,
-                    "midir-read-input",
-                    move |_stamp, message, _| {
-                        let message = MidiMessage::from(message);
-                        let send = || {
-                            let mut u = updated.lock().unwrap();
-                            *u = true;
-                        };
-
-                        // do midi stuff
-                        synth.lock().unwrap().midi_input(&message);
-                        send();
-                    },
-                    (),
-                ),
+            let mut sysex = SysExReceiver::default();
+
+            let connection = midi_in.connect(
+                in_port,
+                "stepper-synth-input",
+                move |stamp, message, _| {
+                    if let Some(clock) = ClockMessage::parse(message) {
+                        if synth.lock().unwrap().clock_input(clock, stamp) {
+                            updated.store(true, Ordering::Relaxed);
+                        }
+
+                        return;
+                    }
+
+                    if message.first() == Some(&SYSEX_START) {
+                        if let Some(dump) = sysex.receive(message) {
+                            synth.lock().unwrap().load_dump(dump);
+                            updated.store(true, Ordering::Relaxed);
+                        }
+
+                        return;
+                    }
+
+                    let message = MidiMessage::from(message);
+
+                    // do midi stuff
+                    synth.lock().unwrap().midi_input(&message);
+                    updated.store(true, Ordering::Relaxed);
+                },
+                (),
             );
+
+            match connection {
+                Ok(connection) => {
+                    info!("connected MIDI port {name}");
+                    failed.remove(name);
+                    connections.insert(name.clone(), connection);
+                }
+                Err(e) if failed.insert(name.clone()) => {
+                    warn!("couldn't connect MIDI port {name}: {e}")
+                }
+                Err(_) => {}
+            }
+        }
+
+        {
+            let mut ports = ports.lock().unwrap();
+            let names: Vec<String> = names.into_iter().flatten().collect();
+            let before = ports.ports().to_vec();
+            ports.update(&names, |name| connections.contains_key(name));
+            ports.dirty = false;
+
+            if ports.ports() != before {
+                updated.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let scanned = Instant::now();
+
+        while scanned.elapsed() < MIDI_RESCAN
+            && !exit.load(Ordering::Relaxed)
+            && !ports.lock().unwrap().dirty
+        {
+            ctrl.recover();
+            sleep(MIDI_POLL);
         }
     }
 
+    for (name, connection) in connections.drain() {
+        connection.close();
+        info!("closed MIDI port {name}");
+    }
+
     Ok(())
 }
 
-fn logger_init() -> Result<()> {
+/// logs to stderr, and to `log_file` too if one is given.
+pub fn logger_init(log_file: Option<PathBuf>) -> Result<()> {
     let colors = ColoredLevelConfig::new()
         .debug(Color::Blue)
         .info(Color::Green)
@@ -232,8 +584,10 @@ fn logger_init() -> Result<()> {
     // .filter(|metadata| metadata.target().starts_with("stepper"))
     // .apply()?;
 
-    #[cfg(target_arch = "aarch64")]
-    let dis = dis.chain(fern::log_file("stepper-synth.log")?);
+    let dis = match log_file {
+        Some(path) => dis.chain(fern::log_file(path)?),
+        None => dis,
+    };
 
     dis.apply()?;
 
@@ -281,6 +635,7 @@ fn stepper_synth_backend(m: &Bound<'_, PyModule>) -> PyResult<()> {
     use pygame_coms::{
         ADSRState, LfoState, LowPassState, OscState, SynthEngineState, WTSynthParam,
     };
+    use render::BitDepth;
 
     m.add_function(wrap_pyfunction!(log_trace, m)?)?;
     m.add_function(wrap_pyfunction!(log_debug, m)?)?;
@@ -311,6 +666,21 @@ fn stepper_synth_backend(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<ADSRState>()?;
     m.add_class::<LfoState>()?;
     m.add_class::<WTSynthParam>()?;
+    m.add_class::<BitDepth>()?;
+    m.add_class::<MixerChannel>()?;
+    m.add_class::<Level>()?;
+    m.add_class::<Levels>()?;
+    m.add_class::<CallbackLoad>()?;
+    m.add_class::<MidiPort>()?;
+    m.add_class::<MidiOutTarget>()?;
+    m.add_class::<ClockSource>()?;
+    m.add_class::<LearnTarget>()?;
+    m.add_class::<MidiBinding>()?;
+    m.add_class::<VelocityCurve>()?;
+    m.add_class::<Velocity>()?;
+    m.add_class::<BendRange>()?;
+    m.add_class::<MpeZones>()?;
+    m.add_class::<PatchSlot>()?;
     // m.add_class::<>()?;
 
     Ok(())
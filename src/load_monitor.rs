@@ -0,0 +1,127 @@
+use crate::{
+    pygame_coms::SynthEngineType,
+    sample_rate,
+    synth_engines::{Synth, SynthEngine},
+};
+use log::*;
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+
+/// how long the rolling max load covers, roughly.
+const WINDOW: Duration = Duration::from_secs(5);
+/// how often the load is written to the log.
+const REPORT_EVERY: Duration = Duration::from_secs(30);
+/// a callback this much later than one buffer after the last one means the device ran dry.
+const LATE: f32 = 1.5;
+
+/// how close the audio callback is to missing its deadline.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CallbackLoad {
+    /// time spent rendering the last buffer over how long it plays for. at 1.0 there's no time to
+    /// spare.
+    pub load: f32,
+    /// the highest load in the last five to ten seconds.
+    pub max_load: f32,
+    /// buffers that took longer to render than they play for, or started late, since startup.
+    pub xruns: u64,
+    pub buffers: u64,
+}
+
+/// times every audio callback. runs on the audio thread.
+#[derive(Debug, Clone)]
+pub struct LoadMonitor {
+    load: CallbackLoad,
+    last_start: Option<Instant>,
+    window_start: Instant,
+    window_max: f32,
+    last_window_max: f32,
+    last_report: Instant,
+    xruns_at_report: u64,
+}
+
+impl LoadMonitor {
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            load: CallbackLoad::default(),
+            last_start: None,
+            window_start: now,
+            window_max: 0.0,
+            last_window_max: 0.0,
+            last_report: now,
+            xruns_at_report: 0,
+        }
+    }
+
+    /// records one callback that started at `start` and rendered `n_frames` with `synth`.
+    pub fn record(&mut self, start: Instant, n_frames: usize, synth: &Synth) -> CallbackLoad {
+        let now = Instant::now();
+        let buffer_len = n_frames as f32 / sample_rate() as f32;
+        let load = (now - start).as_secs_f32() / buffer_len;
+        let late = self
+            .last_start
+            .is_some_and(|last| (start - last).as_secs_f32() > buffer_len * LATE);
+        self.last_start = Some(start);
+
+        if load > 1.0 || late {
+            self.load.xruns += 1;
+
+            // only the first one per report, a struggling device would flood the log.
+            if self.load.xruns == self.xruns_at_report + 1 {
+                warn!(
+                    "xrun: rendered a {:.1} ms buffer in {:.1} ms{} while playing {}",
+                    buffer_len * 1000.0,
+                    (now - start).as_secs_f32() * 1000.0,
+                    if late { ", the callback came late" } else { "" },
+                    active_engines(synth),
+                );
+            }
+        }
+
+        if now - self.window_start > WINDOW {
+            self.last_window_max = self.window_max;
+            self.window_max = 0.0;
+            self.window_start = now;
+        }
+
+        self.window_max = self.window_max.max(load);
+        self.load.load = load;
+        self.load.max_load = self.window_max.max(self.last_window_max);
+        self.load.buffers += 1;
+
+        if now - self.last_report > REPORT_EVERY {
+            info!(
+                "audio load {:.0}%, max {:.0}%, {} xruns in the last {}s",
+                self.load.load * 100.0,
+                self.load.max_load * 100.0,
+                self.load.xruns - self.xruns_at_report,
+                REPORT_EVERY.as_secs(),
+            );
+            self.last_report = now;
+            self.xruns_at_report = self.load.xruns;
+        }
+
+        self.load
+    }
+}
+
+fn active_engines(synth: &Synth) -> String {
+    let engines: Vec<String> = SynthEngineType::iter()
+        .zip(synth.engines.iter())
+        .filter(|(_, engine)| engine.is_active())
+        .map(|(engine_type, _)| engine_type.to_string())
+        .collect();
+
+    if engines.is_empty() {
+        "nothing".into()
+    } else {
+        engines.join(", ")
+    }
+}
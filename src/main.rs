@@ -0,0 +1,191 @@
+use anyhow::Result;
+use clap::Parser;
+use log::*;
+use std::{
+    io::{stdout, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+use stepper_synth_backend::{
+    commands::{SynthCtrl, SynthSnapshot},
+    config::Config,
+    effects::EffectType,
+    load_monitor::CallbackLoad,
+    logger_init,
+    midi_ports::MidiPorts,
+    pygame_coms::SynthEngineType,
+    release_all, run_audio, run_midi,
+    sequencer::{run_sequencer, SequencerIntake},
+    session::Session,
+    set_sample_rate,
+    synth_engines::Synth,
+    AudioTaps,
+};
+
+/// runs the synth without the GUI, as a headless sound module driven over MIDI. flags override the
+/// session, which overrides the config file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// config file to load instead of the one found in the default locations.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// engine to start on.
+    #[arg(short, long)]
+    engine: Option<SynthEngineType>,
+    /// effect to start on.
+    #[arg(short = 'f', long)]
+    effect: Option<EffectType>,
+    /// turn the effect on at startup.
+    #[arg(long)]
+    effect_on: bool,
+    /// session or preset file to load at startup.
+    #[arg(short, long)]
+    session: Option<PathBuf>,
+    /// only connect to MIDI inputs whose name contains this, ignoring case. added to the config's
+    /// allow list.
+    #[arg(short, long)]
+    midi_port: Option<String>,
+    #[arg(long)]
+    sample_rate: Option<u32>,
+    /// frames per audio callback, smaller is lower latency but costs more CPU.
+    #[arg(long)]
+    buffer_size: Option<usize>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let mut config = Config::find(args.config)?;
+
+    if let Err(reason) = logger_init(config.log_file.clone()) {
+        eprintln!("failed to initiate logger because {reason}");
+    }
+
+    config.sample_rate = args.sample_rate.unwrap_or(config.sample_rate);
+    config.buffer_size = args.buffer_size.unwrap_or(config.buffer_size);
+    config.midi_ports.allow.extend(args.midi_port);
+    set_sample_rate(config.sample_rate);
+
+    let session = args.session.map(Session::load).transpose()?;
+    let mut synth = Synth::new();
+    synth.configure(&config);
+
+    if let Some(ref session) = session {
+        synth.load_preset(&session.preset);
+    }
+
+    if let Some(engine) = args.engine {
+        synth.set_engine(engine);
+    }
+
+    if let Some(effect) = args.effect {
+        synth.set_effect(effect);
+    }
+
+    synth.effect_power |= args.effect_on;
+
+    let (ctrl, feed) = SynthCtrl::new(&synth);
+    let levels = Arc::new(Mutex::new(synth.meters.levels()));
+    let load = Arc::new(Mutex::new(CallbackLoad::default()));
+    let updated = ctrl.updated();
+    let exit = Arc::new(AtomicBool::new(false));
+    let mut sequencer = SequencerIntake::new(ctrl.clone());
+    sequencer.configure(&config);
+
+    if let Some(session) = session {
+        sequencer.bpm = session.bpm;
+
+        if !session.sequences.is_empty() {
+            sequencer.set_sequences(session.sequences);
+        }
+    }
+
+    let sequencer = Arc::new(Mutex::new(sequencer));
+    let taps = AudioTaps {
+        levels,
+        load,
+        scope: None,
+    };
+    let device = run_audio(synth, feed, taps, config.buffer_size)?;
+
+    let sequencer_thread = {
+        let seq = sequencer.clone();
+        let exit = exit.clone();
+
+        spawn(move || run_sequencer(seq, exit))
+    };
+
+    let midi_thread = {
+        let seq = sequencer.clone();
+        let updated = updated.clone();
+        let exit = exit.clone();
+        let midi_ports = Arc::new(Mutex::new(MidiPorts::new(config.midi_ports.clone())));
+
+        spawn(move || {
+            if let Err(e) = run_midi(seq, updated, exit, midi_ports) {
+                error!("{e}");
+            }
+        })
+    };
+
+    // set on SIGINT or SIGTERM, `exit` waits until every note has been let go.
+    let quit = Arc::new(AtomicBool::new(false));
+
+    {
+        let quit = quit.clone();
+        ctrlc::set_handler(move || quit.store(true, Ordering::Relaxed))?;
+    }
+
+    info!("Synth is ready to make sound");
+
+    while !quit.load(Ordering::Relaxed) {
+        if updated.swap(false, Ordering::Relaxed) {
+            let status = status_line(&ctrl.snapshot(), &sequencer.lock().unwrap());
+            print!("\r{status}\x1b[K");
+            stdout().flush()?;
+        }
+
+        sleep(Duration::from_millis(50));
+    }
+
+    println!();
+    release_all(&sequencer, config.buffer_size);
+    exit.store(true, Ordering::Relaxed);
+
+    if sequencer_thread.join().is_err() {
+        error!("the sequencer thread panicked");
+    }
+
+    if midi_thread.join().is_err() {
+        error!("the MIDI thread panicked");
+    }
+
+    // the device stops when dropped, after `run_midi` has closed every port.
+    drop(device);
+    info!("shut down cleanly");
+
+    Ok(())
+}
+
+fn status_line(snapshot: &SynthSnapshot, seq: &SequencerIntake) -> String {
+    let transport = if seq.state.playing.load(Ordering::Relaxed) {
+        "playing"
+    } else if seq.state.recording {
+        "recording"
+    } else {
+        "stopped"
+    };
+
+    format!(
+        "{} | {} {} | {} bpm | {transport}",
+        snapshot.engine,
+        snapshot.effect,
+        if snapshot.effect_on { "on" } else { "off" },
+        seq.bpm,
+    )
+}
@@ -0,0 +1,260 @@
+use crate::{sample_rate, StereoFrame};
+use std::f32::consts::PI;
+
+/// the loudest the limiter lets through, just under full scale.
+pub const CEILING: f32 = 0.98;
+/// how far ahead the limiter looks, this is also the latency it adds.
+const LOOKAHEAD: f32 = 0.003;
+const RELEASE: f32 = 0.1;
+/// a sample this loud is a filter or feedback loop blowing up, not music.
+const BLOW_UP_LEVEL: f32 = 100.0;
+/// this loud on average, sustained for about `RUNAWAY_TIME`, is runaway noise the limiter would
+/// otherwise flatten into a wall of full scale.
+const RUNAWAY_LEVEL: f32 = 1.5;
+const RUNAWAY_TIME: f32 = 0.5;
+/// how long the output stays muted after blowing up.
+const MUTE_HOLD: f32 = 0.5;
+const DC_CUTOFF: f32 = 10.0;
+
+/// the last stage before the output: removes DC, limits peaks to `CEILING`, and mutes itself if
+/// the synth starts producing NaNs, absurd peaks, or stays far over full scale.
+#[derive(Debug, Clone)]
+pub struct MasterBus {
+    dc_blocker: [DcBlocker; 2],
+    limiter: Limiter,
+    /// frames left before a muted bus plays again.
+    muted_for: usize,
+    mute_hold: usize,
+    /// the input's mean square, averaged over about `RUNAWAY_TIME`.
+    power: f32,
+    power_coeff: f32,
+    tripped: bool,
+}
+
+impl MasterBus {
+    pub fn new() -> Self {
+        Self {
+            dc_blocker: [DcBlocker::new(), DcBlocker::new()],
+            limiter: Limiter::new(),
+            muted_for: 0,
+            mute_hold: (sample_rate() as f32 * MUTE_HOLD) as usize,
+            power: 0.0,
+            power_coeff: 1.0 - (-1.0 / (sample_rate() as f32 * RUNAWAY_TIME)).exp(),
+            tripped: false,
+        }
+    }
+
+    pub fn process(&mut self, frame: StereoFrame) -> StereoFrame {
+        let blown_up = |sample: f32| !sample.is_finite() || sample.abs() > BLOW_UP_LEVEL;
+        let peak = frame.left.abs().max(frame.right.abs());
+
+        if !blown_up(frame.left) && !blown_up(frame.right) {
+            self.power += (peak * peak - self.power) * self.power_coeff;
+        }
+
+        if blown_up(frame.left) || blown_up(frame.right) || self.power > RUNAWAY_LEVEL.powi(2) {
+            // this runs on the audio thread, so it's up to whoever reads `tripped` to say so.
+            if self.muted_for == 0 {
+                self.reset();
+                self.tripped = true;
+            }
+
+            self.muted_for = self.mute_hold;
+
+            return StereoFrame::default();
+        }
+
+        if self.muted_for > 0 {
+            self.muted_for -= 1;
+
+            return StereoFrame::default();
+        }
+
+        let frame = StereoFrame {
+            left: self.dc_blocker[0].process(frame.left),
+            right: self.dc_blocker[1].process(frame.right),
+        };
+
+        self.limiter.process(frame).map(flush_denormal)
+    }
+
+    pub fn process_block(&mut self, buffer: &mut [StereoFrame]) {
+        for frame in buffer.iter_mut() {
+            *frame = self.process(*frame);
+        }
+    }
+
+    /// true if the bus muted itself since the last call, the caller should clear whatever state
+    /// blew up.
+    pub fn tripped(&mut self) -> bool {
+        std::mem::take(&mut self.tripped)
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted_for > 0
+    }
+
+    fn reset(&mut self) {
+        self.dc_blocker.iter_mut().for_each(DcBlocker::reset);
+        self.limiter.reset();
+        self.power = 0.0;
+    }
+}
+
+/// a one pole high pass well below anything audible.
+#[derive(Debug, Clone, Copy)]
+struct DcBlocker {
+    coeff: f32,
+    last_in: f32,
+    last_out: f32,
+}
+
+impl DcBlocker {
+    fn new() -> Self {
+        Self {
+            coeff: 1.0 - (2.0 * PI * DC_CUTOFF / sample_rate() as f32),
+            last_in: 0.0,
+            last_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let out = sample - self.last_in + self.coeff * self.last_out;
+        self.last_in = sample;
+        // the feedback decays into denormals when the synth goes quiet.
+        self.last_out = flush_denormal(out);
+
+        self.last_out
+    }
+
+    fn reset(&mut self) {
+        self.last_in = 0.0;
+        self.last_out = 0.0;
+    }
+}
+
+/// a look-ahead peak limiter. the gain needed for each incoming frame is held for the length of
+/// the delay line so it has fully come down by the time that frame reaches the output.
+#[derive(Debug, Clone)]
+struct Limiter {
+    delay: Vec<StereoFrame>,
+    pos: usize,
+    /// the lowest gain needed by any frame still in the delay line.
+    held_gain: f32,
+    hold: usize,
+    gain: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+}
+
+impl Limiter {
+    fn new() -> Self {
+        let sample_rate = sample_rate() as f32;
+        let lookahead = ((sample_rate * LOOKAHEAD) as usize).max(1);
+
+        Self {
+            delay: vec![StereoFrame::default(); lookahead],
+            pos: 0,
+            held_gain: 1.0,
+            hold: 0,
+            gain: 1.0,
+            // close enough to the target within the look-ahead, the final clamp catches the rest.
+            attack_coeff: 1.0 - (-4.0 / lookahead as f32).exp(),
+            release_coeff: 1.0 - (-1.0 / (sample_rate * RELEASE)).exp(),
+        }
+    }
+
+    fn process(&mut self, frame: StereoFrame) -> StereoFrame {
+        let peak = frame.left.abs().max(frame.right.abs());
+        let needed = if peak > CEILING { CEILING / peak } else { 1.0 };
+
+        if needed <= self.held_gain {
+            self.held_gain = needed;
+            self.hold = self.delay.len();
+        } else if self.hold > 0 {
+            self.hold -= 1;
+        } else {
+            self.held_gain = needed;
+        }
+
+        let coeff = if self.held_gain < self.gain {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.gain += (self.held_gain - self.gain) * coeff;
+
+        let out = std::mem::replace(&mut self.delay[self.pos], frame);
+        self.pos = (self.pos + 1) % self.delay.len();
+
+        (out * self.gain).map(|sample| sample.clamp(-CEILING, CEILING))
+    }
+
+    fn reset(&mut self) {
+        self.delay.fill(StereoFrame::default());
+        self.pos = 0;
+        self.held_gain = 1.0;
+        self.hold = 0;
+        self.gain = 1.0;
+    }
+}
+
+fn flush_denormal(sample: f32) -> f32 {
+    if sample.is_subnormal() {
+        0.0
+    } else {
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::set_sample_rate;
+
+    /// `seconds` of `sample(i)` through a fresh bus. returns whether it tripped.
+    fn run(seconds: f32, sample: impl Fn(usize) -> f32) -> bool {
+        set_sample_rate(48_000);
+        let mut bus = MasterBus::new();
+        let mut tripped = false;
+
+        for i in 0..(sample_rate() as f32 * seconds) as usize {
+            let out = bus.process(StereoFrame::mono(sample(i)));
+            assert!(out.left.abs() <= CEILING && out.right.abs() <= CEILING);
+            tripped |= bus.tripped();
+        }
+
+        tripped
+    }
+
+    /// noise between `-amp` and `amp` that never drops below `min` in size.
+    fn noise(i: usize, min: f32, amp: f32) -> f32 {
+        let x = (i as u32).wrapping_mul(2_654_435_761) as f32 / u32::MAX as f32;
+        let size = min + (amp - min) * x;
+
+        if i % 2 == 0 {
+            size
+        } else {
+            -size
+        }
+    }
+
+    #[test]
+    fn a_spike_trips() {
+        assert!(run(0.1, |i| if i == 100 { 500.0 } else { 0.0 }));
+        assert!(run(0.1, |i| if i == 100 { f32::NAN } else { 0.0 }));
+    }
+
+    #[test]
+    fn runaway_noise_trips() {
+        assert!(run(1.0, |i| noise(i, 1.0, 10.0)));
+    }
+
+    #[test]
+    fn loud_music_doesnt_trip() {
+        let sine = |i: usize| (i as f32 * 440.0 * 2.0 * PI / sample_rate() as f32).sin();
+
+        assert!(!run(2.0, |i| sine(i) * 1.2));
+        assert!(!run(2.0, |i| noise(i, 0.0, 1.0)));
+    }
+}
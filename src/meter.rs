@@ -0,0 +1,131 @@
+use crate::{sample_rate, StereoFrame};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+
+/// how long the peak hold and clip indicators stay up, in seconds.
+const HOLD: f32 = 1.5;
+/// samples at or above this count as clipping.
+const CLIP_LEVEL: f32 = 1.0;
+
+/// one meter's reading over the last audio buffer, all levels are linear.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub peak: f32,
+    pub rms: f32,
+    /// the highest peak in the last `HOLD` seconds.
+    pub peak_hold: f32,
+    /// true if anything clipped in the last `HOLD` seconds.
+    pub clip: bool,
+}
+
+/// every meter's reading, published by the audio thread once per buffer.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Levels {
+    /// each engine after its fader, indexed like `Synth::engines`.
+    pub engines: Vec<Level>,
+    /// every engine summed and through the effect, before the master bus. if this clips and the
+    /// engines don't, the effect or the sum is too hot.
+    pub mix: Level,
+    /// what reaches the output.
+    pub master: Level,
+}
+
+/// collects samples over a buffer and turns them into a `Level`.
+#[derive(Debug, Default, Clone)]
+pub struct Meter {
+    level: Level,
+    peak: f32,
+    sum_sq: f32,
+    count: usize,
+    hold_for: usize,
+    clip_for: usize,
+}
+
+impl Meter {
+    pub fn add(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.sum_sq += sample * sample;
+        self.count += 1;
+    }
+
+    pub fn add_frame(&mut self, frame: StereoFrame) {
+        self.peak = self.peak.max(frame.left.abs()).max(frame.right.abs());
+        self.sum_sq += (frame.left * frame.left + frame.right * frame.right) * 0.5;
+        self.count += 1;
+    }
+
+    /// ends a buffer `n_frames` long. anything not added during it counts as silence.
+    pub fn finish(&mut self, n_frames: usize) -> Level {
+        let hold_len = (sample_rate() as f32 * HOLD) as usize;
+        let rms = if self.count > 0 {
+            (self.sum_sq / n_frames.max(self.count) as f32).sqrt()
+        } else {
+            0.0
+        };
+
+        if self.peak >= self.level.peak_hold || self.hold_for <= n_frames {
+            self.level.peak_hold = self.peak;
+            self.hold_for = hold_len;
+        } else {
+            self.hold_for -= n_frames;
+        }
+
+        if self.peak >= CLIP_LEVEL {
+            self.clip_for = hold_len;
+        } else {
+            self.clip_for = self.clip_for.saturating_sub(n_frames);
+        }
+
+        self.level.peak = self.peak;
+        self.level.rms = rms;
+        self.level.clip = self.clip_for > 0;
+        self.peak = 0.0;
+        self.sum_sq = 0.0;
+        self.count = 0;
+
+        self.level
+    }
+}
+
+/// all the meters in the synth.
+#[derive(Debug, Clone)]
+pub struct Meters {
+    pub engines: Box<[Meter]>,
+    pub mix: Meter,
+    pub master: Meter,
+}
+
+impl Meters {
+    pub fn new(n_engines: usize) -> Self {
+        Self {
+            engines: vec![Meter::default(); n_engines].into(),
+            mix: Meter::default(),
+            master: Meter::default(),
+        }
+    }
+
+    /// the readings from the last buffer.
+    pub fn levels(&self) -> Levels {
+        Levels {
+            engines: self.engines.iter().map(|meter| meter.level).collect(),
+            mix: self.mix.level,
+            master: self.master.level,
+        }
+    }
+
+    /// ends the buffer on every meter and writes the readings to `levels`, reusing its memory so
+    /// the audio thread doesn't allocate.
+    pub fn finish(&mut self, n_frames: usize, levels: &mut Levels) {
+        levels.engines.clear();
+        levels
+            .engines
+            .extend(self.engines.iter_mut().map(|meter| meter.finish(n_frames)));
+        levels.mix = self.mix.finish(n_frames);
+        levels.master = self.master.finish(n_frames);
+    }
+}
@@ -0,0 +1,175 @@
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// MIDI clock ticks per quarter note. the sequencer plays one step per quarter note.
+pub const PPQN: u32 = 24;
+/// song position pointers count sixteenths, this many to a step.
+const SIXTEENTHS_PER_STEP: u16 = 4;
+/// with no tick for this long the leader is taken to have stopped sending clock.
+const CLOCK_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub const TIMING_CLOCK: u8 = 0xF8;
+pub const START: u8 = 0xFA;
+pub const CONTINUE: u8 = 0xFB;
+pub const STOP: u8 = 0xFC;
+pub const SONG_POSITION: u8 = 0xF2;
+
+/// where the sequencer's tempo comes from.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq, eq_int, hash, frozen)
+)]
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockSource {
+    /// the sequencer keeps its own time from its bpm, and sends MIDI clock if it has an output.
+    #[default]
+    Internal,
+    /// steps advance on MIDI clock from any connected input, and follow its start and stop.
+    External,
+}
+
+/// the MIDI real time and song position messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMessage {
+    Tick,
+    Start,
+    Continue,
+    Stop,
+    /// the position to start from on the next `Continue`, in sixteenths.
+    SongPosition(u16),
+}
+
+impl ClockMessage {
+    /// reads a raw MIDI message, `None` if it isn't clock or transport.
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        match *message {
+            [TIMING_CLOCK, ..] => Some(Self::Tick),
+            [START, ..] => Some(Self::Start),
+            [CONTINUE, ..] => Some(Self::Continue),
+            [STOP, ..] => Some(Self::Stop),
+            [SONG_POSITION, lsb, msb, ..] => Some(Self::SongPosition(
+                (lsb as u16 & 0x7f) | ((msb as u16 & 0x7f) << 7),
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn to_midi(self) -> Vec<u8> {
+        match self {
+            Self::Tick => vec![TIMING_CLOCK],
+            Self::Start => vec![START],
+            Self::Continue => vec![CONTINUE],
+            Self::Stop => vec![STOP],
+            Self::SongPosition(pos) => {
+                vec![SONG_POSITION, (pos & 0x7f) as u8, ((pos >> 7) & 0x7f) as u8]
+            }
+        }
+    }
+
+    /// the song position at the start of `step`.
+    pub fn song_position(step: usize) -> Self {
+        Self::SongPosition((step as u16).saturating_mul(SIXTEENTHS_PER_STEP) & 0x3fff)
+    }
+}
+
+/// the sequencer's clock settings, and the state of the MIDI clock it's following.
+#[derive(Debug, Clone, Default)]
+pub struct MidiClock {
+    pub source: ClockSource,
+    /// the MIDI output clock and transport are sent to while the clock is internal.
+    pub out: Option<String>,
+    /// ticks since the last step, only counted while playing.
+    ticks: u32,
+    /// every tick received, playing or not.
+    received: u64,
+    /// steps clocked through that the sequencer hasn't played yet.
+    steps_due: usize,
+    /// the time stamps of the last beat's ticks, in microseconds.
+    tick_stamps: VecDeque<u64>,
+    last_tick: Option<Instant>,
+}
+
+impl MidiClock {
+    /// counts a tick that arrived at `stamp` microseconds. returns true on every beat, when the
+    /// tempo estimate is worth redrawing.
+    pub fn tick(&mut self, stamp: u64, playing: bool) -> bool {
+        // a different input, or its clock restarted.
+        if self.tick_stamps.back().is_some_and(|last| *last > stamp) {
+            self.tick_stamps.clear();
+        }
+
+        self.tick_stamps.push_back(stamp);
+        self.last_tick = Some(Instant::now());
+
+        if self.tick_stamps.len() > PPQN as usize + 1 {
+            self.tick_stamps.pop_front();
+        }
+
+        self.received += 1;
+
+        if playing {
+            self.ticks += 1;
+
+            if self.ticks >= PPQN {
+                self.ticks = 0;
+                self.steps_due += 1;
+            }
+        }
+
+        self.received % PPQN as u64 == 0
+    }
+
+    /// moves to `sixteenths` into the current step, dropping any steps not played yet.
+    pub fn locate(&mut self, sixteenths: u16) {
+        self.ticks = (sixteenths % SIXTEENTHS_PER_STEP) as u32 * PPQN / SIXTEENTHS_PER_STEP as u32;
+        self.steps_due = 0;
+    }
+
+    /// lets the sequencer play its next step without waiting for the clock.
+    pub fn cue_step(&mut self) {
+        self.steps_due += 1;
+    }
+
+    /// true if a clocked step is waiting to be played, and marks it played.
+    pub fn take_step(&mut self) -> bool {
+        if self.steps_due == 0 {
+            return false;
+        }
+
+        self.steps_due -= 1;
+
+        true
+    }
+
+    /// the tempo of the incoming clock in bpm, averaged over the last beat. `None` if no clock is
+    /// coming in.
+    pub fn tempo(&self) -> Option<f32> {
+        if self
+            .last_tick
+            .is_none_or(|last| last.elapsed() > CLOCK_TIMEOUT)
+        {
+            return None;
+        }
+
+        let (first, last) = (self.tick_stamps.front()?, self.tick_stamps.back()?);
+        let intervals = self.tick_stamps.len() as f32 - 1.0;
+
+        if last <= first {
+            return None;
+        }
+
+        let tick_len = (last - first) as f32 / intervals;
+
+        Some(60_000_000.0 / (tick_len * PPQN as f32))
+    }
+}
+
+/// where a step falls in the song, for a `ClockMessage::SongPosition`.
+pub fn step_at(sixteenths: u16, n_steps: usize) -> usize {
+    (sixteenths / SIXTEENTHS_PER_STEP) as usize % n_steps.max(1)
+}
@@ -0,0 +1,305 @@
+use crate::{
+    effects::{Effect, EffectType, EffectsModule},
+    pygame_coms::{SynthEngineType, WTSynthParam},
+    synth_engines::{Param, Synth, SynthModule},
+    KnobCtrl,
+};
+use anyhow::{bail, Result};
+use log::*;
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    ops::IndexMut,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    thread::spawn,
+};
+
+/// something a learned CC can control.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LearnTarget {
+    /// a knob or GUI parameter of `engine`.
+    Engine {
+        engine: SynthEngineType,
+        param: Param,
+    },
+    /// a wave table engine parameter, the CC replaces its value.
+    WaveTable { param: WTSynthParam },
+    /// a parameter of `effect`, named as in `Effect::get_param_list`.
+    Effect { effect: EffectType, param: String },
+}
+
+impl LearnTarget {
+    /// errors if a CC can't set this target.
+    pub fn check(&self) -> Result<()> {
+        match self {
+            Self::Engine { .. } => {}
+            Self::WaveTable { param } => {
+                if param.with_value(0.0).is_none() {
+                    bail!("{param:?} doesn't take a value a CC can set");
+                }
+            }
+            Self::Effect { effect, param } => {
+                if !EffectsModule::from(*effect)
+                    .get_param_list()
+                    .contains(param)
+                {
+                    bail!("{effect:?} has no parameter called {param}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// a CC bound to a target. the CC's 0 to 127 is scaled to `min` to `max`.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiBinding {
+    /// the MIDI channel, from 0.
+    pub channel: u8,
+    pub cc: u8,
+    pub target: LearnTarget,
+    #[serde(default)]
+    pub min: f32,
+    #[serde(default = "default_max")]
+    pub max: f32,
+}
+
+fn default_max() -> f32 {
+    1.0
+}
+
+impl MidiBinding {
+    pub fn scale(&self, value: u8) -> f32 {
+        self.min + (value.min(127) as f32 / 127.0) * (self.max - self.min)
+    }
+}
+
+/// how the bindings are written to disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BindingsFile {
+    #[serde(default)]
+    bindings: Vec<MidiBinding>,
+}
+
+/// the learned CCs, and the target waiting for one while learning.
+#[derive(Debug, Clone, Default)]
+pub struct MidiLearn {
+    bindings: Vec<MidiBinding>,
+    /// bound to the next CC that arrives, with the range it gets.
+    learning: Option<(LearnTarget, f32, f32)>,
+    /// takes the bindings file whenever the bindings change and writes it on its own thread, so
+    /// binding a CC from the MIDI input callback never waits on the disk.
+    writer: Option<Sender<String>>,
+}
+
+impl MidiLearn {
+    /// loads the bindings saved at `path`, and saves there from now on. a missing file is no
+    /// bindings.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let bindings = path
+            .as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| match Self::read(path) {
+                Ok(bindings) => Some(bindings),
+                Err(e) => {
+                    warn!("couldn't load the MIDI bindings at {}: {e}", path.display());
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            bindings,
+            learning: None,
+            writer: path.map(Self::writer),
+        }
+    }
+
+    /// starts the thread that writes the bindings to `path`, it ends when the last `Sender` goes.
+    fn writer(path: PathBuf) -> Sender<String> {
+        let (tx, rx) = channel::<String>();
+
+        spawn(move || {
+            for toml in rx {
+                if let Err(e) = fs::write(&path, toml) {
+                    warn!("couldn't save the MIDI bindings to {}: {e}", path.display());
+                }
+            }
+        });
+
+        tx
+    }
+
+    fn read(path: &Path) -> Result<Vec<MidiBinding>> {
+        let file: BindingsFile = toml::from_str(&fs::read_to_string(path)?)?;
+
+        Ok(file.bindings)
+    }
+
+    fn save(&self) {
+        let Some(writer) = self.writer.as_ref() else {
+            return;
+        };
+
+        let file = BindingsFile {
+            bindings: self.bindings.clone(),
+        };
+
+        match toml::to_string_pretty(&file) {
+            Ok(toml) => {
+                if writer.send(toml).is_err() {
+                    warn!("the MIDI bindings writer stopped, the bindings weren't saved");
+                }
+            }
+            Err(e) => warn!("couldn't save the MIDI bindings: {e}"),
+        }
+    }
+
+    pub fn bindings(&self) -> &[MidiBinding] {
+        &self.bindings
+    }
+
+    pub fn learning(&self) -> Option<&LearnTarget> {
+        self.learning.as_ref().map(|(target, _, _)| target)
+    }
+
+    /// binds the next CC to `target`, scaled to `min` to `max`.
+    pub fn learn(&mut self, target: LearnTarget, min: f32, max: f32) -> Result<()> {
+        target.check()?;
+        info!("learning a CC for {target:?}");
+        self.learning = Some((target, min, max));
+
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) {
+        self.learning = None;
+    }
+
+    /// removes the binding for `cc` on `channel`.
+    pub fn unlearn(&mut self, channel: u8, cc: u8) {
+        self.bindings
+            .retain(|binding| binding.channel != channel || binding.cc != cc);
+        self.save();
+    }
+
+    /// binds `cc` on `channel` to the target being learned. returns false if nothing is being
+    /// learned.
+    pub fn bind(&mut self, channel: u8, cc: u8) -> bool {
+        let Some((target, min, max)) = self.learning.take() else {
+            return false;
+        };
+
+        info!("bound CC {cc} on channel {channel} to {target:?}");
+        self.bindings
+            .retain(|binding| binding.channel != channel || binding.cc != cc);
+        self.bindings.push(MidiBinding {
+            channel,
+            cc,
+            target,
+            min,
+            max,
+        });
+        self.save();
+
+        true
+    }
+
+    pub fn binding(&self, channel: u8, cc: u8) -> Option<&MidiBinding> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.channel == channel && binding.cc == cc)
+    }
+}
+
+impl WTSynthParam {
+    /// this parameter with its value replaced by `value`. `None` for the ones that don't have a
+    /// single value, like adding to the mod matrix.
+    pub fn with_value(&self, value: f32) -> Option<Self> {
+        let param = match self.clone() {
+            Self::OscVol { n, to: _ } => Self::OscVol { n, to: value },
+            Self::OscOn { n, on: _ } => Self::OscOn {
+                n,
+                on: value >= 0.5,
+            },
+            Self::OscDetune { n, detune: _ } => Self::OscDetune { n, detune: value },
+            Self::OscOffset { n, offset: _ } => Self::OscOffset {
+                n,
+                offset: value.round() as i16,
+            },
+            Self::OscTarget { n, target: _ } => Self::OscTarget {
+                n,
+                target: value.round() as i8,
+            },
+            Self::LowPassCutoff { n, cutoff: _ } => Self::LowPassCutoff { n, cutoff: value },
+            Self::LowPassRes { n, res: _ } => Self::LowPassRes { n, res: value },
+            Self::LowPassTracking { n, track: _ } => Self::LowPassTracking {
+                n,
+                track: value >= 0.5,
+            },
+            Self::LowPassMix { n, mix: _ } => Self::LowPassMix { n, mix: value },
+            Self::ADSRAttack { n, val: _ } => Self::ADSRAttack { n, val: value },
+            Self::ADSRDecay { n, val: _ } => Self::ADSRDecay { n, val: value },
+            Self::ADSRSustain { n, val: _ } => Self::ADSRSustain { n, val: value },
+            Self::ADSRRelease { n, val: _ } => Self::ADSRRelease { n, val: value },
+            Self::LfoSpeed { n, speed: _ } => Self::LfoSpeed { n, speed: value },
+            Self::ModMatrixMod {
+                id,
+                src,
+                dest,
+                amt: _,
+                bipolar,
+            } => Self::ModMatrixMod {
+                id,
+                src,
+                dest,
+                amt: value,
+                bipolar,
+            },
+            Self::OscWaveTable { .. } | Self::ModMatrixAdd { .. } | Self::ModMatrixDel { .. } => {
+                return None
+            }
+        };
+
+        Some(param)
+    }
+}
+
+impl Synth {
+    /// sets a learned target to `value`. returns true if the GUI visible state changed.
+    pub fn set_target(&mut self, target: &LearnTarget, value: f32) -> bool {
+        match target {
+            LearnTarget::Engine {
+                engine,
+                param: Param::Knob(knob),
+            } => self.engines[*engine as usize].knob(*knob, value),
+            LearnTarget::Engine {
+                engine,
+                param: Param::Gui(param),
+            } => self.engines[*engine as usize].gui_param(*param, value),
+            LearnTarget::WaveTable { param } => {
+                let Some(param) = param.with_value(value) else {
+                    return false;
+                };
+
+                if let SynthModule::WaveTable(wt) =
+                    self.engines.index_mut(SynthEngineType::WaveTable as usize)
+                {
+                    wt.set_param(param);
+                }
+
+                true
+            }
+            LearnTarget::Effect { effect, param } => {
+                self.effects[*effect as usize].set_param(param, value);
+                true
+            }
+        }
+    }
+}
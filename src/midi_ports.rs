@@ -0,0 +1,159 @@
+use crate::{config::PortFilter, HashMap};
+use log::*;
+use midir::{MidiOutput, MidiOutputConnection};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use std::time::{Duration, Instant};
+
+/// the name the synth shows up as to other MIDI software. ports with this in their name are the
+/// synth's own and are never connected, so it can't feed itself.
+pub const MIDI_CLIENT_NAME: &str = "stepper-synth";
+
+/// how long to wait before trying a MIDI output that wouldn't connect again.
+const OUTPUT_RETRY: Duration = Duration::from_secs(2);
+
+/// a MIDI input the synth has seen.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiPort {
+    pub name: String,
+    /// whether the synth will connect to it, from the config or switched at runtime.
+    pub enabled: bool,
+    pub connected: bool,
+}
+
+/// every MIDI input that's plugged in, shared between `run_midi` and the GUI.
+#[derive(Debug, Clone, Default)]
+pub struct MidiPorts {
+    filter: PortFilter,
+    /// ports switched on or off at runtime, these win over `filter`.
+    overrides: HashMap<String, bool>,
+    ports: Vec<MidiPort>,
+    /// set when something changed that `run_midi` should act on before its next scan.
+    pub(crate) dirty: bool,
+}
+
+impl MidiPorts {
+    pub fn new(filter: PortFilter) -> Self {
+        Self {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    pub fn ports(&self) -> &[MidiPort] {
+        &self.ports
+    }
+
+    pub fn enabled(&self, name: &str) -> bool {
+        if name.contains(MIDI_CLIENT_NAME) {
+            return false;
+        }
+
+        self.overrides
+            .get(name)
+            .copied()
+            .unwrap_or_else(|| self.filter.allows(name))
+    }
+
+    /// connect to, or disconnect from, `name` regardless of the config. lasts until the synth exits.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        self.overrides.insert(name.to_string(), enabled);
+        self.dirty = true;
+    }
+
+    /// replaces the port list with what was just scanned.
+    pub(crate) fn update(&mut self, names: &[String], connected: impl Fn(&str) -> bool) {
+        self.ports = names
+            .iter()
+            .map(|name| MidiPort {
+                name: name.clone(),
+                enabled: self.enabled(name),
+                connected: connected(name),
+            })
+            .collect();
+    }
+}
+
+/// connections to external MIDI outputs, each one is opened the first time something is sent to
+/// it. a port is picked by the first output whose name contains the given name, ignoring case.
+#[derive(Default)]
+pub struct MidiOutputs {
+    connections: HashMap<String, MidiOutputConnection>,
+    /// when each port that wouldn't connect was last tried.
+    failed: HashMap<String, Instant>,
+}
+
+impl MidiOutputs {
+    pub fn send(&mut self, port: &str, message: &[u8]) {
+        if !self.connections.contains_key(port) && !self.connect(port) {
+            return;
+        }
+
+        let Some(connection) = self.connections.get_mut(port) else {
+            return;
+        };
+
+        if let Err(e) = connection.send(message) {
+            // most likely unplugged, reconnect on the next message.
+            warn!("sending to MIDI output {port} failed: {e}");
+            self.connections.remove(port);
+        }
+    }
+
+    fn connect(&mut self, port: &str) -> bool {
+        if self
+            .failed
+            .get(port)
+            .is_some_and(|tried| tried.elapsed() < OUTPUT_RETRY)
+        {
+            return false;
+        }
+
+        let connection = MidiOutput::new(MIDI_CLIENT_NAME)
+            .map_err(|e| e.to_string())
+            .and_then(|midi_out| {
+                let pattern = port.to_lowercase();
+                let out_port = midi_out
+                    .ports()
+                    .into_iter()
+                    .find(|out_port| {
+                        midi_out.port_name(out_port).is_ok_and(|name| {
+                            !name.contains(MIDI_CLIENT_NAME)
+                                && name.to_lowercase().contains(&pattern)
+                        })
+                    })
+                    .ok_or_else(|| "no output has that name".to_string())?;
+
+                midi_out
+                    .connect(&out_port, "stepper-synth-output")
+                    .map_err(|e| e.to_string())
+            });
+
+        match connection {
+            Ok(connection) => {
+                info!("connected MIDI output {port}");
+                self.failed.remove(port);
+                self.connections.insert(port.to_string(), connection);
+
+                true
+            }
+            Err(e) => {
+                if !self.failed.contains_key(port) {
+                    warn!("couldn't connect MIDI output {port}: {e}");
+                }
+
+                self.failed.insert(port.to_string(), Instant::now());
+
+                false
+            }
+        }
+    }
+
+    pub fn close(&mut self) {
+        for (port, connection) in self.connections.drain() {
+            connection.close();
+            info!("closed MIDI output {port}");
+        }
+    }
+}
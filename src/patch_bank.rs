@@ -0,0 +1,201 @@
+use crate::session::Preset;
+use anyhow::Result;
+use log::*;
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    thread::spawn,
+};
+
+pub const BANK_SELECT_MSB_CC: u8 = 0;
+pub const BANK_SELECT_LSB_CC: u8 = 32;
+
+/// a preset stored where Program Change can recall it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BankPatch {
+    /// bank select MSB and LSB together, `msb << 7 | lsb`.
+    pub bank: u16,
+    pub program: u8,
+    #[serde(default)]
+    pub name: String,
+    pub preset: Preset,
+}
+
+/// where a stored patch is, without the preset, for the GUI to list.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchSlot {
+    pub bank: u16,
+    pub program: u8,
+    pub name: String,
+}
+
+/// how the bank is written to disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BankFile {
+    #[serde(default)]
+    patches: Vec<BankPatch>,
+}
+
+/// the stored patches, and the bank each MIDI channel has selected.
+#[derive(Debug, Clone)]
+pub struct PatchBank {
+    patches: Vec<BankPatch>,
+    /// the bank select MSB and LSB last sent on each channel.
+    selected: [[u8; 2]; 16],
+    /// takes the bank file whenever the patches change and writes it on its own thread, so storing
+    /// a patch never holds up the GUI, or the lock it's stored under, while the disk catches up.
+    writer: Option<Sender<String>>,
+}
+
+impl Default for PatchBank {
+    fn default() -> Self {
+        Self {
+            patches: Vec::new(),
+            selected: [[0; 2]; 16],
+            writer: None,
+        }
+    }
+}
+
+impl PatchBank {
+    /// loads the patches saved at `path`, and saves there from now on. a missing file is an empty
+    /// bank.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let patches = path
+            .as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| match Self::read(path) {
+                Ok(patches) => Some(patches),
+                Err(e) => {
+                    warn!("couldn't load the patch bank at {}: {e}", path.display());
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            patches,
+            writer: path.map(Self::writer),
+            ..Self::default()
+        }
+    }
+
+    /// starts the thread that writes the bank to `path`, it ends when the last `Sender` goes.
+    fn writer(path: PathBuf) -> Sender<String> {
+        let (tx, rx) = channel::<String>();
+
+        spawn(move || {
+            for toml in rx {
+                if let Err(e) = fs::write(&path, toml) {
+                    warn!("couldn't save the patch bank to {}: {e}", path.display());
+                }
+            }
+        });
+
+        tx
+    }
+
+    fn read(path: &Path) -> Result<Vec<BankPatch>> {
+        let file: BankFile = toml::from_str(&fs::read_to_string(path)?)?;
+
+        Ok(file.patches)
+    }
+
+    fn save(&self) {
+        let Some(writer) = self.writer.as_ref() else {
+            return;
+        };
+
+        let file = BankFile {
+            patches: self.patches.clone(),
+        };
+
+        match toml::to_string_pretty(&file) {
+            Ok(toml) => {
+                if writer.send(toml).is_err() {
+                    warn!("the patch bank writer stopped, the bank wasn't saved");
+                }
+            }
+            Err(e) => warn!("couldn't save the patch bank: {e}"),
+        }
+    }
+
+    pub fn slots(&self) -> Vec<PatchSlot> {
+        let mut slots: Vec<PatchSlot> = self
+            .patches
+            .iter()
+            .map(|patch| PatchSlot {
+                bank: patch.bank,
+                program: patch.program,
+                name: patch.name.clone(),
+            })
+            .collect();
+        slots.sort_by_key(|slot| (slot.bank, slot.program));
+
+        slots
+    }
+
+    /// stores `preset` at `program` in `bank`, replacing what was there.
+    pub fn store(&mut self, bank: u16, program: u8, name: String, preset: Preset) {
+        let (bank, program) = (bank & 0x3fff, program & 0x7f);
+        info!("storing patch {name:?} at bank {bank}, program {program}");
+        self.patches
+            .retain(|patch| patch.bank != bank || patch.program != program);
+        self.patches.push(BankPatch {
+            bank,
+            program,
+            name,
+            preset,
+        });
+        self.save();
+    }
+
+    pub fn remove(&mut self, bank: u16, program: u8) {
+        self.patches
+            .retain(|patch| patch.bank != bank || patch.program != program);
+        self.save();
+    }
+
+    pub fn get(&self, bank: u16, program: u8) -> Option<&BankPatch> {
+        self.patches
+            .iter()
+            .find(|patch| patch.bank == bank && patch.program == program)
+    }
+
+    /// keeps track of bank select on `channel`. returns true if `control` was one.
+    pub fn bank_select(&mut self, channel: u8, control: u8, value: u8) -> bool {
+        let selected = &mut self.selected[channel as usize & 0xf];
+
+        match control {
+            BANK_SELECT_MSB_CC => selected[0] = value & 0x7f,
+            BANK_SELECT_LSB_CC => selected[1] = value & 0x7f,
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// the bank `channel` has selected.
+    pub fn selected(&self, channel: u8) -> u16 {
+        let [msb, lsb] = self.selected[channel as usize & 0xf];
+
+        (msb as u16) << 7 | lsb as u16
+    }
+
+    /// the patch a Program Change to `program` on `channel` recalls.
+    pub fn program_change(&self, channel: u8, program: u8) -> Option<&BankPatch> {
+        let bank = self.selected(channel);
+        let patch = self.get(bank, program);
+
+        if patch.is_none() {
+            warn!("no patch stored at bank {bank}, program {program}");
+        }
+
+        patch
+    }
+}
@@ -1,34 +1,55 @@
+#[cfg(feature = "pyo3")]
 use crate::{
-    effects::{Effect, EffectType},
+    commands::{SynthCmd, SynthCtrl},
+    config::Config,
+    load_monitor::CallbackLoad,
     logger_init,
-    sequencer::{Sequence, SequencerIntake, Step},
-    synth_engines::{wave_table::WaveTableEngine, Synth, SynthEngine, SynthModule},
-    HashMap, KnobCtrl, SampleGen, SAMPLE_RATE,
+    meter::Levels,
+    midi_learn::{LearnTarget, MidiBinding},
+    midi_ports::{MidiOutputs, MidiPort, MidiPorts},
+    patch_bank::PatchSlot,
+    release_all,
+    render::{render_to_wav, BitDepth, RenderSettings},
+    run_audio, run_midi,
+    scope::{scope_tap, Scope},
+    sequencer::{run_sequencer, MidiOutTarget, SequencerIntake},
+    session::Session,
+    set_sample_rate,
+    synth_engines::{
+        mpe::MpeZones,
+        synth_common::velocity::{Velocity, VelocityCurve},
+        BendRange, MixerChannel, Synth,
+    },
+    sysex::{Dump, BYTE_TIME},
+    AudioTaps,
+};
+use crate::{
+    effects::EffectType,
+    midi_clock::ClockSource,
+    sequencer::{Sequence, Step},
+    synth_engines::wave_table::WaveTableEngine,
+    HashMap,
 };
 #[cfg(feature = "pyo3")]
-use crate::{run_midi, sequencer::play_sequence};
-use anyhow::{bail, Result};
+use anyhow::anyhow;
+#[cfg(feature = "pyo3")]
 use log::*;
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+#[cfg(feature = "pyo3")]
 use std::{
-    fmt::Display,
-    ops::IndexMut,
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
     thread::{sleep, spawn, JoinHandle},
-    time::Duration,
 };
-use strum::EnumIter;
-use tinyaudio::prelude::*;
+use strum::{EnumIter, IntoEnumIterator};
 use wavetable_synth::{
-    common::{
-        EnvParam, LfoParam, LowPass, LowPassParam, ModMatrixDest, ModMatrixItem, ModMatrixSrc,
-        OscParam,
-    },
+    common::{EnvParam, LfoParam, LowPass, LowPassParam, ModMatrixDest, ModMatrixSrc, OscParam},
     synth_engines::{
         synth::osc::OscTarget,
         synth_common::env::{ATTACK, DECAY, RELEASE, SUSTAIN},
@@ -55,7 +76,9 @@ pub enum GuiParam {
     feature = "pyo3",
     pyclass(module = "stepper_synth_backend", get_all, eq, eq_int, hash, frozen)
 )]
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash, EnumIter, Serialize, Deserialize,
+)]
 pub enum Knob {
     One,
     Two,
@@ -106,6 +129,20 @@ impl Display for SynthEngineType {
     }
 }
 
+impl FromStr for SynthEngineType {
+    type Err = String;
+
+    /// takes either the variant name or the display name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::iter()
+            .find(|engine| {
+                s.eq_ignore_ascii_case(&format!("{engine:?}"))
+                    || s.eq_ignore_ascii_case(&format!("{engine}"))
+            })
+            .ok_or_else(|| format!("unknown synth engine {s}"))
+    }
+}
+
 #[cfg(feature = "pyo3")]
 #[cfg_attr(feature = "pyo3", pymethods)]
 impl SynthEngineType {
@@ -147,8 +184,8 @@ pub struct OscState {
     target: String,
 }
 
-impl From<WaveTableEngine> for Vec<OscState> {
-    fn from(value: WaveTableEngine) -> Self {
+impl From<&WaveTableEngine> for Vec<OscState> {
+    fn from(value: &WaveTableEngine) -> Self {
         value.synth.voices[0]
             .lock()
             .unwrap()
@@ -181,8 +218,8 @@ pub struct LowPassState {
     mix: f32,
 }
 
-impl From<WaveTableEngine> for Vec<LowPassState> {
-    fn from(value: WaveTableEngine) -> Self {
+impl From<&WaveTableEngine> for Vec<LowPassState> {
+    fn from(value: &WaveTableEngine) -> Self {
         value.synth.voices[0]
             .lock()
             .unwrap()
@@ -207,8 +244,8 @@ pub struct ADSRState {
     rel: f32,
 }
 
-impl From<WaveTableEngine> for Vec<ADSRState> {
-    fn from(value: WaveTableEngine) -> Self {
+impl From<&WaveTableEngine> for Vec<ADSRState> {
+    fn from(value: &WaveTableEngine) -> Self {
         value.synth.voices[0]
             .lock()
             .unwrap()
@@ -230,8 +267,8 @@ pub struct LfoState {
     speed: f32,
 }
 
-impl From<WaveTableEngine> for Vec<LfoState> {
-    fn from(value: WaveTableEngine) -> Self {
+impl From<&WaveTableEngine> for Vec<LfoState> {
+    fn from(value: &WaveTableEngine) -> Self {
         value.synth.voices[0]
             .lock()
             .unwrap()
@@ -254,8 +291,10 @@ pub struct ModMatrixDisplayItem {
     pub id: usize,
 }
 
-impl From<WaveTableEngine> for Vec<ModMatrixDisplayItem> {
-    fn from(value: WaveTableEngine) -> Self {
+impl From<&WaveTableEngine> for Vec<ModMatrixDisplayItem> {
+    fn from(value: &WaveTableEngine) -> Self {
+        let first_aftertouch = value.mod_matrix_len();
+
         value
             .synth
             .mod_matrix
@@ -277,10 +316,42 @@ impl From<WaveTableEngine> for Vec<ModMatrixDisplayItem> {
                     None
                 }
             })
+            .chain(value.aftertouch_mods.iter().enumerate().map(|(i, entry)| {
+                ModMatrixDisplayItem {
+                    src: "Aftertouch".into(),
+                    dest: display_dest(entry.dest),
+                    amt: entry.amt,
+                    bipolar: entry.bipolar,
+                    id: first_aftertouch + i,
+                }
+            }))
             .collect()
     }
 }
 
+/// what the GUI shows of the wave table engine. it's built from `SynthCtrl`'s copy of the synth
+/// so the GUI never touches the voices being rendered.
+#[derive(Debug, Clone)]
+pub struct WaveTableState {
+    pub osc: Vec<OscState>,
+    pub filter: Vec<LowPassState>,
+    pub adsr: Vec<ADSRState>,
+    pub lfo: Vec<LfoState>,
+    pub mod_matrix: Vec<ModMatrixDisplayItem>,
+}
+
+impl From<&WaveTableEngine> for WaveTableState {
+    fn from(value: &WaveTableEngine) -> Self {
+        Self {
+            osc: value.into(),
+            filter: value.into(),
+            adsr: value.into(),
+            lfo: value.into(),
+            mod_matrix: value.into(),
+        }
+    }
+}
+
 fn display_src(src: ModMatrixSrc) -> String {
     match src {
         ModMatrixSrc::Gate => "Gate".into(),
@@ -361,6 +432,10 @@ pub enum StepperSynthState {
         recording: bool,
         cursor: usize,
         tempo: u16,
+        /// where the tempo comes from, with `External` the tempo shown should be `clock_tempo`.
+        clock: ClockSource,
+        /// the tempo of the incoming MIDI clock, `None` when none is coming in.
+        clock_tempo: Option<f32>,
         step: Step,
         sequence: Sequence,
         seq_n: usize,
@@ -376,7 +451,7 @@ pub enum StepperSynthState {
 }
 
 #[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum WTSynthParam {
     OscVol {
         n: usize,
@@ -449,128 +524,163 @@ pub enum WTSynthParam {
         id: usize,
     },
     ModMatrixMod {
-        /// the id of the mod matrix entry to modify (i.e its index + 1)
+        /// the id of the mod matrix entry to modify (i.e its index + 1). the aftertouch mods come
+        /// after the mod matrix entries.
         id: usize,
         src: String,
         dest: String,
         amt: f32,
         bipolar: bool,
     },
-    // MidiLearn {},
-    // MidiUnearn {},
 }
 
 #[cfg(feature = "pyo3")]
 #[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend"))]
 #[derive(Debug)]
 pub struct StepperSynth {
-    // synth: Arc<Mutex<Synth>>,
-    updated: Arc<Mutex<bool>>,
+    synth: SynthCtrl,
+    updated: Arc<AtomicBool>,
+    levels: Arc<Mutex<Levels>>,
+    load: Arc<Mutex<CallbackLoad>>,
+    scope: Mutex<Scope>,
+    midi_ports: Arc<Mutex<MidiPorts>>,
     screen: Screen,
-    _handle: JoinHandle<()>,
-    _midi_thread: JoinHandle<()>,
+    /// owns the audio device and the MIDI connections.
+    audio_thread: Option<JoinHandle<()>>,
+    sequencer_thread: Option<JoinHandle<()>>,
     exit: Arc<AtomicBool>,
+    /// frames per audio callback, shutdown waits for a couple of them to play out.
+    buffer_size: usize,
     pub midi_sequencer: Arc<Mutex<SequencerIntake>>,
 }
 
 #[cfg(feature = "pyo3")]
 impl StepperSynth {
-    pub fn new() -> Self {
-        // build synth in arc mutex
-        let synth = Synth::new();
-        let sequencer = Arc::new(Mutex::new(SequencerIntake::new(synth)));
+    pub fn new(config: Config) -> Self {
+        if let Err(reason) = logger_init(config.log_file.clone()) {
+            eprintln!("failed to initiate logger because {reason}");
+        }
 
-        let updated = Arc::new(Mutex::new(true));
+        // every DSP struct reads the sample rate as it's built, so set it before making the synth.
+        set_sample_rate(config.sample_rate);
+
+        // the synth is owned by the audio thread, everything else talks to it through `ctrl`.
+        let mut synth = Synth::new();
+        synth.configure(&config);
+        let (ctrl, feed) = SynthCtrl::new(&synth);
+        let levels = Arc::new(Mutex::new(synth.meters.levels()));
+        let load = Arc::new(Mutex::new(CallbackLoad::default()));
+        let (scope_tx, scope) = scope_tap();
+        let mut sequencer = SequencerIntake::new(ctrl.clone());
+        sequencer.configure(&config);
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let buffer_size = config.buffer_size;
+        let midi_ports = Arc::new(Mutex::new(MidiPorts::new(config.midi_ports.clone())));
+
+        let updated = ctrl.updated();
         let exit = Arc::new(AtomicBool::new(false));
         // let effect_midi = Arc::new(AtomicBool::new(false));
 
-        let handle = {
+        let audio_thread = {
             let seq = sequencer.clone();
-            // let synth = synth.clone();
+            let taps = AudioTaps {
+                levels: levels.clone(),
+                load: load.clone(),
+                scope: Some(scope_tx),
+            };
             let updated = updated.clone();
             let exit = exit.clone();
+            let midi_ports = midi_ports.clone();
             // let effect_midi = effect_midi.clone();
 
             spawn(move || {
-                let params = OutputDeviceParameters {
-                    channels_count: 1,
-                    sample_rate: SAMPLE_RATE as usize,
-                    // channel_sample_count: 2048,
-                    channel_sample_count: 1024,
-                };
-                let device = run_output_device(params, {
-                    let seq = seq.clone();
+                let device = run_audio(synth, feed, taps, buffer_size);
 
-                    move |data| {
-                        for samples in data.chunks_mut(params.channels_count) {
-                            let value = seq.lock().expect("couldn't lock synth").synth.get_sample();
-
-                            for sample in samples {
-                                *sample = value;
-                            }
-                        }
-                    }
-                });
-
-                if let Err(e) = device {
+                if let Err(e) = &device {
                     error!("starting audio playback caused error: {e}");
                 }
 
                 // let seq = sequencer.clone();
 
-                if let Err(e) = run_midi(seq, updated, exit) {
+                if let Err(e) = run_midi(seq, updated, exit, midi_ports) {
                     error!("{e}");
                 }
+
+                // the device stops when dropped, after `run_midi` has closed every port.
+                if let Ok(device) = device {
+                    drop(device);
+                    info!("closed the audio device");
+                }
             })
         };
 
-        let thread = {
+        let sequencer_thread = {
             let seq = sequencer.clone();
+            let exit = exit.clone();
 
-            spawn(move || loop {
-                while !seq
-                    .clone()
-                    .lock()
-                    .unwrap()
-                    .state
-                    .playing
-                    .load(Ordering::Relaxed)
-                {
-                    sleep(Duration::from_secs_f64(0.001));
-                }
-
-                play_sequence(seq.clone());
-            })
+            spawn(move || run_sequencer(seq, exit))
         };
 
-        if let Err(reason) = logger_init() {
-            eprintln!("failed to initiate logger because {reason}");
-        }
-
         info!("Synth is ready to make sound");
 
         Self {
-            // synth,
+            synth: ctrl,
             updated,
-            screen: Screen::Synth(SynthEngineType::B3Organ),
-            _handle: handle,
-            _midi_thread: thread,
+            levels,
+            load,
+            scope: Mutex::new(scope),
+            midi_ports,
+            screen: Screen::Synth(config.engine),
+            audio_thread: Some(audio_thread),
+            sequencer_thread: Some(sequencer_thread),
             midi_sequencer: sequencer,
             exit,
+            buffer_size,
             // effect_midi,
         }
     }
 
     pub fn get_engine_state(&self) -> SynthEngineState {
-        let mut seq = self.midi_sequencer.lock().unwrap();
+        let snapshot = self.synth.snapshot();
 
         SynthEngineState {
-            engine: seq.synth.engine_type,
-            effect: seq.synth.effect_type,
-            effect_on: seq.synth.effect_power,
-            knob_params: seq.synth.get_engine().get_params(),
-            gui_params: seq.synth.get_engine().get_gui_params(),
+            engine: snapshot.engine,
+            effect: snapshot.effect,
+            effect_on: snapshot.effect_on,
+            knob_params: snapshot.knob_params.clone(),
+            gui_params: snapshot.gui_params.clone(),
+        }
+    }
+
+    /// stops the transport, releases every note, closes the MIDI ports and the audio device, then
+    /// waits for every thread to finish. does nothing the second time.
+    fn shutdown(&mut self) {
+        let (Some(audio_thread), Some(sequencer_thread)) =
+            (self.audio_thread.take(), self.sequencer_thread.take())
+        else {
+            return;
+        };
+
+        release_all(&self.midi_sequencer, self.buffer_size);
+        self.exit.store(true, Ordering::Relaxed);
+
+        if sequencer_thread.join().is_err() {
+            error!("the sequencer thread panicked");
+        }
+
+        if audio_thread.join().is_err() {
+            error!("the audio thread panicked");
         }
+
+        info!("shut down cleanly");
+    }
+}
+
+#[cfg(feature = "pyo3")]
+impl Drop for StepperSynth {
+    fn drop(&mut self) {
+        // python may never call `exit`, the ALSA handles still have to be let go.
+        self.shutdown();
     }
 }
 
@@ -578,30 +688,210 @@ impl StepperSynth {
 #[cfg_attr(feature = "pyo3", pymethods)]
 impl StepperSynth {
     #[cfg(feature = "pyo3")]
+    /// `config` is the path to a config file, see `Config::default_path` for where it's looked for
+    /// otherwise. `sample_rate` and `buffer_size` override the config.
     #[new]
-    pub fn new_py() -> Self {
-        Self::new()
+    #[pyo3(signature = (sample_rate=None, buffer_size=None, config=None))]
+    pub fn new_py(
+        sample_rate: Option<u32>,
+        buffer_size: Option<usize>,
+        config: Option<PathBuf>,
+    ) -> Self {
+        // the logger isn't up until the config says where it goes.
+        let mut config = Config::find(config).unwrap_or_else(|e| {
+            eprintln!("failed to load the config, using the defaults: {e}");
+            Config::default()
+        });
+        config.sample_rate = sample_rate.unwrap_or(config.sample_rate);
+        config.buffer_size = buffer_size.unwrap_or(config.buffer_size);
+
+        Self::new(config)
     }
 
     pub fn exit(&mut self) {
         warn!("GoodBye");
-        self.exit.store(true, Ordering::Relaxed);
+        self.shutdown();
     }
 
     pub fn updated(&self) -> bool {
-        *self.updated.lock().unwrap()
+        self.updated.load(Ordering::Relaxed)
+    }
+
+    /// how hard the audio thread is working, and how many buffers it has dropped.
+    pub fn get_load(&self) -> CallbackLoad {
+        *self.load.lock().unwrap()
+    }
+
+    /// the meters as of the last audio buffer.
+    pub fn get_levels(&self) -> Levels {
+        self.levels.lock().unwrap().clone()
+    }
+
+    /// the live waveform, `width` points covering about 20 ms.
+    #[pyo3(signature = (width=256))]
+    pub fn get_scope(&self, width: usize) -> Vec<f32> {
+        self.scope.lock().unwrap().frame(width)
+    }
+
+    /// the live spectrum in dB, `n_bins` evenly spaced bins from 0 Hz to half the sample rate.
+    #[pyo3(signature = (n_bins=256))]
+    pub fn get_spectrum(&self, n_bins: usize) -> Vec<f32> {
+        self.scope.lock().unwrap().spectrum(n_bins)
+    }
+
+    /// watch `engine` after its fader on the scope and spectrum, or the output if `None`.
+    #[pyo3(signature = (engine=None))]
+    pub fn set_scope_source(&mut self, engine: Option<SynthEngineType>) {
+        self.synth.send(SynthCmd::ScopeSource(engine));
+    }
+
+    /// every MIDI input that's plugged in, and whether it's connected.
+    pub fn get_midi_ports(&self) -> Vec<MidiPort> {
+        self.midi_ports.lock().unwrap().ports().to_vec()
+    }
+
+    /// bind the next CC that comes in to `target`, its range scaled to `min` to `max`.
+    #[pyo3(signature = (target, min=0.0, max=1.0))]
+    pub fn midi_learn(&mut self, target: LearnTarget, min: f32, max: f32) -> anyhow::Result<()> {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .learn
+            .learn(target, min, max)
+    }
+
+    pub fn cancel_midi_learn(&mut self) {
+        self.midi_sequencer.lock().unwrap().learn.cancel();
+    }
+
+    /// what's waiting for a CC, `None` if not learning.
+    pub fn get_midi_learning(&self) -> Option<LearnTarget> {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .learn
+            .learning()
+            .cloned()
+    }
+
+    pub fn get_midi_bindings(&self) -> Vec<MidiBinding> {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .learn
+            .bindings()
+            .to_vec()
+    }
+
+    /// forget what `cc` on `channel`, from 0, was bound to.
+    pub fn midi_unlearn(&mut self, channel: u8, cc: u8) {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .learn
+            .unlearn(channel, cc);
+    }
+
+    /// store the synth as it sounds now for Program Change `program` to recall in `bank`.
+    #[pyo3(signature = (bank, program, name=String::new()))]
+    pub fn store_patch(&mut self, bank: u16, program: u8, name: String) -> anyhow::Result<()> {
+        let preset = self.synth.preset();
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .patches
+            .store(bank, program, name, preset);
+
+        Ok(())
+    }
+
+    pub fn remove_patch(&mut self, bank: u16, program: u8) {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .patches
+            .remove(bank, program);
+    }
+
+    /// every stored patch, in bank then program order.
+    pub fn get_patches(&self) -> Vec<PatchSlot> {
+        self.midi_sequencer.lock().unwrap().patches.slots()
+    }
+
+    /// load the patch stored at `program` in `bank`, as if a Program Change had asked for it.
+    pub fn recall_patch(&mut self, bank: u16, program: u8) -> anyhow::Result<()> {
+        let preset = self
+            .midi_sequencer
+            .lock()
+            .unwrap()
+            .patches
+            .get(bank, program)
+            .map(|patch| patch.preset.clone())
+            .ok_or_else(|| anyhow!("no patch stored at bank {bank}, program {program}"))?;
+        self.synth.send(SynthCmd::LoadPreset(Box::new(preset)));
+        self.set_updated();
+
+        Ok(())
+    }
+
+    /// connect to, or disconnect from, the MIDI input called `name` until the synth exits.
+    pub fn set_midi_port_enabled(&mut self, name: &str, enabled: bool) {
+        self.midi_ports.lock().unwrap().set_enabled(name, enabled);
+    }
+
+    /// send every step of sequence `sequence` to the MIDI output matching `port` on `channel`, or
+    /// back to the internal engines if `port` is `None`.
+    #[pyo3(signature = (sequence, port=None, channel=0))]
+    pub fn set_sequence_midi_out(&mut self, sequence: usize, port: Option<String>, channel: u8) {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .set_sequence_midi_out(sequence, port.map(|port| MidiOutTarget { port, channel }));
+        self.set_updated();
+    }
+
+    /// send steps recorded on channel `ch` to the MIDI output matching `port` on `channel`, or back
+    /// to the internal engines if `port` is `None`.
+    #[pyo3(signature = (ch, port=None, channel=0))]
+    pub fn set_channel_midi_out(&mut self, ch: u8, port: Option<String>, channel: u8) {
+        self.midi_sequencer
+            .lock()
+            .unwrap()
+            .set_channel_midi_out(ch, port.map(|port| MidiOutTarget { port, channel }));
+        self.set_updated();
+    }
+
+    /// every engine's mixer channel, in `SynthEngineType` order.
+    pub fn get_mixer(&self) -> Vec<MixerChannel> {
+        self.synth.snapshot().mixer.to_vec()
+    }
+
+    /// the engine each MIDI channel plays, from channel 0. `None` plays the engine on screen.
+    pub fn get_midi_routes(&self) -> Vec<Option<SynthEngineType>> {
+        self.synth.snapshot().midi_routes.to_vec()
+    }
+
+    /// how `engine` responds to velocity, `None` if it can't be changed.
+    pub fn get_velocity(&self, engine: SynthEngineType) -> Option<Velocity> {
+        self.synth.snapshot().velocity[engine as usize]
+    }
+
+    pub fn get_mpe_zones(&self) -> MpeZones {
+        self.synth.snapshot().mpe
+    }
+
+    /// how far the pitch wheel bends `engine`, in semitones each way.
+    pub fn get_bend_range(&self, engine: SynthEngineType) -> BendRange {
+        self.synth.snapshot().bend_ranges[engine as usize]
     }
 
     pub fn toggle_effect_power(&mut self) {
-        {
-            let mut seq = self.midi_sequencer.lock().unwrap();
-            seq.synth.effect_power = !seq.synth.effect_power;
-        }
+        self.synth.send(SynthCmd::EffectToggle);
         self.set_updated();
     }
 
     fn set_updated(&mut self) {
-        (*self.updated.lock().unwrap()) = true;
+        self.updated.store(true, Ordering::Relaxed);
     }
 
     pub fn set_screen(&mut self, screen: Screen) {
@@ -612,12 +902,12 @@ impl StepperSynth {
             Screen::Effect(effect) => {
                 self.set_effect(effect);
                 // self.effect_midi.store(true, Ordering::Relaxed)
-                self.midi_sequencer.lock().unwrap().synth.target_effects = true;
+                self.synth.send(SynthCmd::TargetEffects(true));
             }
             Screen::Synth(engine) => {
                 self.set_engine(engine);
                 // self.effect_midi.store(false, Ordering::Relaxed)
-                self.midi_sequencer.lock().unwrap().synth.target_effects = false;
+                self.synth.send(SynthCmd::TargetEffects(false));
             }
             Screen::Stepper(seq) => {
                 self.midi_sequencer.lock().unwrap().set_rec_head_seq(seq);
@@ -625,7 +915,7 @@ impl StepperSynth {
             Screen::WaveTableSynth() => {
                 self.set_engine(SynthEngineType::WaveTable);
                 // self.effect_midi.store(false, Ordering::Relaxed)
-                self.midi_sequencer.lock().unwrap().synth.target_effects = false;
+                self.synth.send(SynthCmd::TargetEffects(false));
             }
         }
 
@@ -640,10 +930,10 @@ impl StepperSynth {
 
     pub fn get_state(&self) -> Option<StepperSynthState> {
         // info!("get_state called");
-        (*self.updated.lock().unwrap()) = false;
+        self.updated.store(false, Ordering::Relaxed);
         // info!("after set");
 
-        let mut seq = self.midi_sequencer.lock().unwrap();
+        let snapshot = self.synth.snapshot();
 
         match self.screen {
             // Screen::Synth(SynthEngineType::B3Organ) => StepperSynthState::Synth {
@@ -662,20 +952,20 @@ impl StepperSynth {
             // },
             Screen::Synth(engine_type) => Some(StepperSynthState::Synth {
                 engine: engine_type,
-                effect: seq.synth.effect_type,
-                effect_on: seq.synth.effect_power,
-                knob_params: seq.synth.get_engine().get_params(),
-                gui_params: seq.synth.get_engine().get_gui_params(),
+                effect: snapshot.effect,
+                effect_on: snapshot.effect_on,
+                knob_params: snapshot.knob_params.clone(),
+                gui_params: snapshot.gui_params.clone(),
             }),
             Screen::Effect(EffectType::Reverb) => Some(StepperSynthState::Effect {
                 effect: EffectType::Reverb,
-                effect_on: seq.synth.effect_power,
-                params: seq.synth.get_effect().get_params(),
+                effect_on: snapshot.effect_on,
+                params: snapshot.effect_params.clone(),
             }),
             Screen::Effect(EffectType::Chorus) => Some(StepperSynthState::Effect {
                 effect: EffectType::Chorus,
-                effect_on: seq.synth.effect_power,
-                params: seq.synth.get_effect().get_params(),
+                effect_on: snapshot.effect_on,
+                params: snapshot.effect_params.clone(),
             }),
             // Screen::Effect(EffectType::Delay) => StepperSynthState::Effect {
             //     effect: EffectType::Delay,
@@ -686,6 +976,7 @@ impl StepperSynth {
                 // if !seq.state.recording {
                 // seq.rec_head.set
                 // }
+                let mut seq = self.midi_sequencer.lock().unwrap();
                 seq.set_sequence(sequence.abs() as usize);
 
                 Some(StepperSynthState::MidiStepper {
@@ -693,6 +984,8 @@ impl StepperSynth {
                     recording: seq.state.recording,
                     name: seq.get_name(),
                     tempo: seq.bpm,
+                    clock: seq.clock.source,
+                    clock_tempo: seq.clock.tempo(),
                     step: seq.get_step(false),
                     cursor: seq.get_cursor(false),
                     sequence: seq.get_sequence(),
@@ -700,17 +993,16 @@ impl StepperSynth {
                 })
             }
             Screen::WaveTableSynth() => {
-                let synth = seq.synth.get_engine();
-
-                let SynthModule::WaveTable(wt) = synth else {
+                let Some(ref wt) = snapshot.wave_table else {
                     return None;
                 };
-
-                let osc: Vec<OscState> = Vec::from(wt.clone());
-                let adsr: Vec<ADSRState> = Vec::from(wt.clone());
-                let filter: Vec<LowPassState> = Vec::from(wt.clone());
-                let lfo: Vec<LfoState> = Vec::from(wt.clone());
-                let mod_matrix: Vec<ModMatrixDisplayItem> = Vec::from(wt.clone());
+                let WaveTableState {
+                    osc,
+                    filter,
+                    adsr,
+                    lfo,
+                    mod_matrix,
+                } = wt.clone();
 
                 Some(StepperSynthState::WaveTable {
                     osc,
@@ -724,58 +1016,90 @@ impl StepperSynth {
     }
 
     pub fn set_engine(&mut self, engine: SynthEngineType) {
-        if self.midi_sequencer.lock().unwrap().synth.set_engine(engine) {
-            self.set_updated();
-        }
+        self.synth.send(SynthCmd::SetEngine(engine));
+        self.set_updated();
     }
 
     pub fn set_effect(&mut self, effect: EffectType) {
-        if self.midi_sequencer.lock().unwrap().synth.set_effect(effect) {
-            self.set_updated();
-        }
+        self.synth.send(SynthCmd::SetEffect(effect));
+        self.set_updated();
     }
 
-    pub fn set_gui_param(&mut self, param: GuiParam, value: f32) {
-        self.set_updated();
-        let mut seq = self.midi_sequencer.lock().unwrap();
+    /// pans `engine` between -1.0 (left) and 1.0 (right).
+    pub fn set_pan(&mut self, engine: SynthEngineType, pan: f32) {
+        self.synth.send(SynthCmd::Pan { engine, pan });
+    }
 
-        match param {
-            GuiParam::A => seq.synth.get_engine().gui_param_1(value),
-            GuiParam::B => seq.synth.get_engine().gui_param_2(value),
-            GuiParam::C => seq.synth.get_engine().gui_param_3(value),
-            GuiParam::D => seq.synth.get_engine().gui_param_4(value),
-            GuiParam::E => seq.synth.get_engine().gui_param_5(value),
-            GuiParam::F => seq.synth.get_engine().gui_param_6(value),
-            GuiParam::G => seq.synth.get_engine().gui_param_7(value),
-            GuiParam::H => seq.synth.get_engine().gui_param_8(value),
+    /// sets the mixer level of `engine` between 0.0 and 1.0.
+    pub fn set_level(&mut self, engine: SynthEngineType, level: f32) {
+        self.synth.send(SynthCmd::Level { engine, level });
+    }
+
+    pub fn set_mute(&mut self, engine: SynthEngineType, mute: bool) {
+        self.synth.send(SynthCmd::Mute { engine, mute });
+    }
+
+    /// play MIDI `channel`, from 0, on `engine`, or on the engine on screen if `None`.
+    #[pyo3(signature = (channel, engine=None))]
+    pub fn set_midi_route(&mut self, channel: u8, engine: Option<SynthEngineType>) {
+        self.synth.send(SynthCmd::MidiRoute { channel, engine });
+    }
+
+    /// sets how `engine` responds to velocity. `amp` and `cutoff` are from 0.0 to 1.0, `cutoff`
+    /// only does anything on engines with a filter.
+    pub fn set_velocity(
+        &mut self,
+        engine: SynthEngineType,
+        curve: VelocityCurve,
+        amp: f32,
+        cutoff: f32,
+    ) {
+        let velocity = Velocity {
+            curve,
+            amp: amp.clamp(0.0, 1.0),
+            cutoff: cutoff.clamp(0.0, 1.0),
         };
+
+        self.synth.send(SynthCmd::Velocity { engine, velocity });
+        self.set_updated();
+    }
+
+    /// gives the lower MPE zone `lower` member channels and the upper zone `upper`. 0 turns a zone
+    /// off.
+    pub fn set_mpe_zones(&mut self, lower: u8, upper: u8) {
+        self.synth
+            .send(SynthCmd::MpeZones(MpeZones { lower, upper }));
+        self.set_updated();
+    }
+
+    /// sets how many semitones the pitch wheel bends `engine` `up` and `down`, up to 48.
+    pub fn set_bend_range(&mut self, engine: SynthEngineType, up: f32, down: f32) {
+        self.synth.send(SynthCmd::BendRange {
+            engine,
+            range: BendRange { up, down },
+        });
+        self.set_updated();
+    }
+
+    pub fn set_gui_param(&mut self, param: GuiParam, value: f32) {
+        self.set_updated();
+        self.synth.send(SynthCmd::Gui { param, value });
     }
 
     pub fn set_knob_param(&mut self, param: Knob, value: f32) {
         self.set_updated();
-        let mut seq = self.midi_sequencer.lock().unwrap();
-        let synth = &mut seq.synth;
-
-        match (param, self.screen) {
-            (Knob::One, Screen::Synth(_)) => synth.get_engine().knob_1(value),
-            (Knob::Two, Screen::Synth(_)) => synth.get_engine().knob_2(value),
-            (Knob::Three, Screen::Synth(_)) => synth.get_engine().knob_3(value),
-            (Knob::Four, Screen::Synth(_)) => synth.get_engine().knob_4(value),
-            (Knob::Five, Screen::Synth(_)) => synth.get_engine().knob_5(value),
-            (Knob::Six, Screen::Synth(_)) => synth.get_engine().knob_6(value),
-            (Knob::Seven, Screen::Synth(_)) => synth.get_engine().knob_7(value),
-            (Knob::Eight, Screen::Synth(_)) => synth.get_engine().knob_8(value),
-            (Knob::One, Screen::Effect(_)) => synth.get_effect().knob_1(value),
-            (Knob::Two, Screen::Effect(_)) => synth.get_effect().knob_2(value),
-            (Knob::Three, Screen::Effect(_)) => synth.get_effect().knob_3(value),
-            (Knob::Four, Screen::Effect(_)) => synth.get_effect().knob_4(value),
-            (Knob::Five, Screen::Effect(_)) => synth.get_effect().knob_5(value),
-            (Knob::Six, Screen::Effect(_)) => synth.get_effect().knob_6(value),
-            (Knob::Seven, Screen::Effect(_)) => synth.get_effect().knob_7(value),
-            (Knob::Eight, Screen::Effect(_)) => synth.get_effect().knob_8(value),
-            (_, Screen::Stepper(_)) => false,
-            _ => false,
+
+        let effect = match self.screen {
+            Screen::Synth(_) => false,
+            Screen::Effect(_) => true,
+            _ => return,
         };
+
+        self.synth.send(SynthCmd::Knob {
+            effect,
+            knob: param,
+            value,
+        });
     }
 
     // /// increments the record head to the next step
@@ -820,6 +1144,29 @@ impl StepperSynth {
         self.midi_sequencer.lock().unwrap().state.recording = false;
     }
 
+    /// starts the sequencer from where it stopped rather than the first step.
+    pub fn continue_playing(&mut self) {
+        self.set_updated();
+        let mut seq = self.midi_sequencer.lock().unwrap();
+
+        seq.state.resume = true;
+        seq.state.recording = false;
+        seq.state.playing.store(true, Ordering::Relaxed);
+    }
+
+    /// keep time internally or follow incoming MIDI clock, from the next time the sequencer starts.
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.midi_sequencer.lock().unwrap().clock.source = source;
+        self.set_updated();
+    }
+
+    /// send MIDI clock and transport to the output matching `port` while keeping time internally,
+    /// or to nothing if `None`. takes effect the next time the sequencer starts.
+    #[pyo3(signature = (port=None))]
+    pub fn set_clock_out(&mut self, port: Option<String>) {
+        self.midi_sequencer.lock().unwrap().clock.out = port;
+    }
+
     pub fn prev_sequence(&mut self) {
         self.midi_sequencer.lock().unwrap().prev_sequence();
         self.set_updated();
@@ -878,277 +1225,97 @@ impl StepperSynth {
 
     pub fn wt_param_setter(&mut self, param: WTSynthParam) {
         self.set_updated();
-        let mut seq = self.midi_sequencer.lock().unwrap();
-        let synth = &mut seq.synth;
-
-        let SynthModule::WaveTable(wt_synth) = synth.get_engine() else {
-            return;
-        };
+        self.synth.send(SynthCmd::WaveTable(param));
+    }
 
-        match param {
-            WTSynthParam::OscOn { n, on } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().oscs[n].1 = on);
-            }
-            WTSynthParam::OscVol { n, to } => wt_synth
-                .synth
-                .voices
-                .iter()
-                .for_each(|v| v.lock().unwrap().oscs.index_mut(n).0.level = to),
-            WTSynthParam::OscDetune { n, detune } => wt_synth
-                .synth
-                .voices
-                .iter()
-                .for_each(|v| v.lock().unwrap().oscs[n].0.detune = detune),
-            WTSynthParam::OscWaveTable {
-                n: _,
-                wave_table: _,
-            } => {
-                // wt_synth
-                //     .synth
-                //     .voices
-                //     .iter()
-                //     .for_each(|v| v.lock().unwrap().oscs[n].0.wave_table = wave_table);
-                // TODO: make happen
-            }
-            WTSynthParam::OscOffset { n, offset } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().oscs[n].0.offset = offset);
-            }
-            WTSynthParam::OscTarget { n: _, target: _ } => {
-                // wt_synth
-                // .synth
-                // .voices
-                // .iter()
-                // .for_each(|v| v.lock().unwrap().oscs[n].0.target += target);
-                // TODO: make happen
-            }
-            WTSynthParam::LowPassCutoff { n, cutoff } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().filters[n].cutoff = cutoff);
-            }
-            WTSynthParam::LowPassRes { n, res } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().filters[n].resonance = res);
-            }
-            WTSynthParam::LowPassMix { n, mix } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().filters[n].mix = mix);
-            }
-            WTSynthParam::LowPassTracking { n, track } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().filters[n].key_track = track);
+    /// bounces the sequence under the record head, or every sequence, to a WAV file at `path`
+    /// using a copy of the synth as it sounds right now.
+    #[pyo3(signature = (path, bit_depth=BitDepth::Int24, loops=1, tail=2.0, all_sequences=false))]
+    pub fn render_wav(
+        &self,
+        path: String,
+        bit_depth: BitDepth,
+        loops: usize,
+        tail: f32,
+        all_sequences: bool,
+    ) -> anyhow::Result<()> {
+        let mut synth = self.synth.state().detached_clone();
+
+        let (sequences, bpm) = {
+            let seq = self.midi_sequencer.lock().unwrap();
+
+            if all_sequences {
+                (seq.get_sequences(), seq.bpm)
+            } else {
+                (vec![seq.get_sequence()], seq.bpm)
             }
-            WTSynthParam::ADSRAttack { n, val } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().envs[n].set_atk(val));
-            }
-            WTSynthParam::ADSRDecay { n, val } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().envs[n].set_decay(val));
-            }
-            WTSynthParam::ADSRSustain { n, val } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().envs[n].set_sus(val));
-            }
-            WTSynthParam::ADSRRelease { n, val } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().envs[n].set_release(val));
-            }
-            WTSynthParam::LfoSpeed { n, speed } => {
-                wt_synth
-                    .synth
-                    .voices
-                    .iter()
-                    .for_each(|v| v.lock().unwrap().lfos[n].set_frequency(1.0 / speed));
-            }
-            WTSynthParam::ModMatrixAdd {
-                src,
-                dest,
-                amt,
-                bipolar,
-            } => {
-                let Ok(src) = str_to_mod_src(&src) else {
-                    error!("the source {src:?} failed to convert to ModMatrixSrc");
-                    return;
-                };
-                // let Ok(dest) = str_to_mod_dest(&dest) else {
-                //     error!("the destination {dest:?} failed to convert to ModMatrixDest");
-                //     return;
-                // };
-                // let s = ModMatrixSrc::Velocity;
-                // warn!("{:?}", toml::to_string_pretty(&s));
-
-                // let Ok(src) = toml::from_str::<ModMatrixSrc>(&src) else {
-                //     error!("the source {src:?} failed to convert to ModMatrixSrc");
-                //     return;
-                // };
-                // info!("src => {src:?}");
-
-                // let d = ModMatrixDest::SynthVolume;
-                // warn!("{:?}", toml::to_string_pretty(&d));
-
-                let dest = if dest.to_string().to_lowercase().starts_with("vol") {
-                    ModMatrixDest::SynthVolume
-                } else {
-                    let Ok(dest) = toml::from_str::<ModMatrixDest>(&dest) else {
-                        error!("the destination {dest:?} failed to convert to ModMatrixDest");
-                        return;
-                    };
+        };
 
-                    dest
-                };
-                // info!("dest => {dest:?}");
-                let matrix_item = ModMatrixItem {
-                    src,
-                    dest,
-                    amt,
-                    bipolar,
-                };
+        let settings = RenderSettings {
+            bpm,
+            loops,
+            tail,
+            bit_depth,
+        };
 
-                info!("adding matrix item {matrix_item:?} to the mod_matrix");
+        render_to_wav(&mut synth, &sequences, &settings, path)
+    }
 
-                for item in wt_synth.synth.mod_matrix.iter_mut() {
-                    if item.is_none() {
-                        *item = Some(matrix_item);
-                        break;
-                    }
-                }
-            }
-            WTSynthParam::ModMatrixDel { id } => {
-                let mut to_rm = [id].to_vec();
-
-                loop {
-                    let Some(id) = to_rm.pop() else {
-                        break;
-                    };
-
-                    let matrix = wt_synth.synth.mod_matrix.clone();
-
-                    // rm the identified matrix entry & scootch everything after it down.
-                    for i in (id + 1)..matrix.len() {
-                        wt_synth.synth.mod_matrix[i - 1] = matrix[i];
-                    }
-
-                    // if any matrix entries modulate the amount of a matrix entry with an id GREATER
-                    // then the rm'ed id adjust to account for the shift from the above for loop.
-                    //
-                    // if any matrix entries modulate the amount of a matrix entry with an id EQUAL to
-                    // that of the rm'ed id rm them too.
-                    for (i, item) in wt_synth.synth.mod_matrix.iter_mut().enumerate() {
-                        if let Some(ref mut entry) = item {
-                            match entry.dest {
-                                ModMatrixDest::ModMatrixEntryModAmt(ref mut n) => {
-                                    if *n > id {
-                                        *n -= 1
-                                    } else if *n == id {
-                                        // // recurse
-                                        // self.wt_param_setter(WTSynthParam::ModMatrixDel { id: i })
-                                        to_rm.push(i);
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-            }
-            WTSynthParam::ModMatrixMod {
-                id,
-                src,
-                dest,
-                amt,
-                bipolar,
-            } => {
-                let Ok(src) = str_to_mod_src(&src) else {
-                    error!("the source {src:?} failed to convert to ModMatrixSrc");
-                    return;
-                };
-                // let Ok(dest) = str_to_mod_dest(&dest) else {
-                //     error!("the destination {dest:?} failed to convert to ModMatrixDest");
-                //     return;
-                // };
-                // let Ok(src) = toml::from_str::<ModMatrixSrc>(&src) else {
-                //     error!("the source {src:?} failed to convert to ModMatrixSrc");
-                //     return;
-                // };
-                let Ok(dest) = toml::from_str::<ModMatrixDest>(&dest) else {
-                    error!("the destination {dest:?} failed to convert to ModMatrixDest");
-                    return;
-                };
-                let matrix_item = ModMatrixItem {
-                    src,
-                    dest,
-                    amt,
-                    bipolar,
-                };
+    /// saves the synth's settings and every sequence to a TOML file at `path`.
+    pub fn save_session(&self, path: String) -> anyhow::Result<()> {
+        let preset = self.synth.preset();
+        let seq = self.midi_sequencer.lock().unwrap();
 
-                wt_synth.synth.mod_matrix[id - 1] = Some(matrix_item);
-            }
-            _ => {}
+        Session {
+            bpm: seq.bpm,
+            preset,
+            sequences: seq.get_sequences(),
         }
+        .save(path)
     }
-}
 
-fn str_to_mod_src(src: &str) -> Result<ModMatrixSrc> {
-    let src = src.trim().to_lowercase();
-
-    if src.starts_with("env-") {
-        let n: usize = src.split("-").collect::<Vec<_>>()[1].parse()?;
+    pub fn load_session(&mut self, path: String) -> anyhow::Result<()> {
+        let session = Session::load(path)?;
+        self.midi_sequencer.lock().unwrap().load_session(session);
+        self.set_updated();
 
-        return Ok(ModMatrixSrc::Env(n));
+        Ok(())
     }
 
-    if src.starts_with("lfo-") {
-        let n: usize = src.split("-").collect::<Vec<_>>()[1].parse()?;
+    /// sends the synth's sound, or the whole session if `session` is set, as SysEx to the MIDI
+    /// output matching `port`. another stepper synth listening there loads it.
+    #[pyo3(signature = (port, session=false))]
+    pub fn send_sysex_dump(&self, port: String, session: bool) -> anyhow::Result<()> {
+        let preset = self.synth.preset();
+        let dump = if session {
+            let seq = self.midi_sequencer.lock().unwrap();
+
+            Dump::Session(Box::new(Session {
+                bpm: seq.bpm,
+                preset,
+                sequences: seq.get_sequences(),
+            }))
+        } else {
+            Dump::Preset(Box::new(preset))
+        };
+        let messages = dump.to_sysex()?;
+
+        // paced to what a DIN cable carries, which can take seconds for a session.
+        spawn(move || {
+            let mut midi_out = MidiOutputs::default();
+            info!(
+                "sending a SysEx dump of {} packets to {port}",
+                messages.len()
+            );
+
+            for message in messages {
+                midi_out.send(&port, &message);
+                sleep(BYTE_TIME * message.len() as u32);
+            }
 
-        return Ok(ModMatrixSrc::Lfo(n));
-    }
+            midi_out.close();
+        });
 
-    Ok(match src.as_str() {
-        "velocity" | "vel" => ModMatrixSrc::Velocity,
-        "gate" => ModMatrixSrc::Gate,
-        "mod-wheel" | "mod-whl" => ModMatrixSrc::ModWheel,
-        "pitch-wheel" | "pitch-whl" => ModMatrixSrc::PitchWheel,
-        "macro-1" | "macro1" | "m-1" | "m1" => ModMatrixSrc::Macro1,
-        "macro-2" | "macro2" | "m-2" | "m2" => ModMatrixSrc::Macro1,
-        "macro-3" | "macro3" | "m-3" | "m3" => ModMatrixSrc::Macro1,
-        "macro-4" | "macro4" | "m-4" | "m4" => ModMatrixSrc::Macro1,
-        // "" => ModMatrixSrc::,
-        _ => bail!(""),
-    })
+        Ok(())
+    }
 }
-
-// fn str_to_mod_dest(dest: &str) -> Result<ModMatrixDest> {
-//
-// }
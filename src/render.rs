@@ -0,0 +1,207 @@
+use crate::{
+    commands::{SynthCtrl, SynthFeed},
+    sample_rate,
+    sequencer::{step_cmd, MidiMessages, Sequence, StepCmd},
+    synth_engines::{
+        pedals::{PEDAL_DOWN, SOSTENUTO_CC, SUSTAIN_CC},
+        Synth,
+    },
+    HashSet, SampleGen, StereoFrame,
+};
+use anyhow::{bail, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::*;
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// how samples are stored in a rendered WAV file.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq, eq_int, hash, frozen)
+)]
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitDepth {
+    Int16,
+    #[default]
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    fn spec(&self) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            Self::Int16 => (16, SampleFormat::Int),
+            Self::Int24 => (24, SampleFormat::Int),
+            Self::Float32 => (32, SampleFormat::Float),
+        };
+
+        WavSpec {
+            channels: 2,
+            sample_rate: sample_rate(),
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub bpm: u16,
+    /// how many times each sequence is played before moving on to the next.
+    pub loops: usize,
+    /// seconds rendered after the last step so releases and reverb tails can ring out.
+    pub tail: f32,
+    pub bit_depth: BitDepth,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            bpm: 120,
+            loops: 1,
+            tail: 2.0,
+            bit_depth: BitDepth::default(),
+        }
+    }
+}
+
+/// plays `sequences` back to back through `synth` as fast as it can and returns the audio. steps
+/// are timed exactly as `play_sequence` times them, one step per beat.
+pub fn render(
+    synth: &mut Synth,
+    sequences: &[Sequence],
+    settings: &RenderSettings,
+) -> Result<Vec<StereoFrame>> {
+    if settings.bpm == 0 {
+        bail!("can't render at 0 bpm");
+    }
+
+    let step_len = sample_rate() as f64 * 60.0 / settings.bpm as f64;
+    let tail_len = (sample_rate() as f32 * settings.tail.max(0.0)) as usize;
+    let n_steps: usize = sequences.iter().map(|seq| seq.steps.len()).sum();
+    let mut frames = Vec::with_capacity((step_len * (n_steps * settings.loops) as f64) as usize);
+    let mut playing = HashSet::default();
+    // pedals a step put down, lifted before the tail so held notes ring out instead of hanging.
+    let mut pedals = HashSet::default();
+    let mut last_on_exit = MidiMessages::default();
+    let mut step_i = 0;
+    // commands go through a queue as they do live, so a trip is recovered from the same way.
+    let (ctrl, mut feed) = SynthCtrl::new(synth);
+
+    for sequence in sequences {
+        for _ in 0..settings.loops {
+            for step in sequence.steps.iter() {
+                send_step(&ctrl, &mut playing, &mut pedals, &last_on_exit);
+                send_step(&ctrl, &mut playing, &mut pedals, &step.on_enter);
+                last_on_exit = step.on_exit.clone();
+
+                // round the end of each step rather than its length so the tempo doesn't drift.
+                step_i += 1;
+                let end = (step_len * step_i as f64).round() as usize;
+                let start = frames.len();
+                frames.resize(end, StereoFrame::default());
+                play(synth, &ctrl, &mut feed, &mut frames[start..]);
+            }
+        }
+    }
+
+    send_step(&ctrl, &mut playing, &mut pedals, &last_on_exit);
+
+    for (ch, note) in playing.drain() {
+        if let Some(cmd) = step_cmd(ch, &StepCmd::Stop { note }) {
+            ctrl.send(cmd);
+        }
+    }
+
+    for (ch, code) in pedals.drain() {
+        if let Some(cmd) = step_cmd(ch, &StepCmd::CC { code, value: 0 }) {
+            ctrl.send(cmd);
+        }
+    }
+
+    let start = frames.len();
+    frames.resize(start + tail_len, StereoFrame::default());
+    play(synth, &ctrl, &mut feed, &mut frames[start..]);
+
+    Ok(frames)
+}
+
+/// runs what `ctrl` sent, then fills `frames`.
+fn play(synth: &mut Synth, ctrl: &SynthCtrl, feed: &mut SynthFeed, frames: &mut [StereoFrame]) {
+    feed.apply(synth);
+    synth.get_frames(frames);
+    feed.check(synth);
+    ctrl.recover();
+}
+
+fn send_step(
+    ctrl: &SynthCtrl,
+    playing: &mut HashSet<(u8, u8)>,
+    pedals: &mut HashSet<(u8, u8)>,
+    midi_s: &MidiMessages,
+) {
+    for (ch, cmd) in midi_s.iter() {
+        match cmd {
+            StepCmd::Play { note, vel: _ } => playing.insert((*ch, *note)),
+            StepCmd::Stop { note } => playing.remove(&(*ch, *note)),
+            StepCmd::CC { code, value } if *code == SUSTAIN_CC || *code == SOSTENUTO_CC => {
+                if *value >= PEDAL_DOWN {
+                    pedals.insert((*ch, *code))
+                } else {
+                    pedals.remove(&(*ch, *code))
+                }
+            }
+            StepCmd::CC { code: _, value: _ } => false,
+        };
+
+        if let Some(cmd) = step_cmd(*ch, cmd) {
+            ctrl.send(cmd);
+        }
+    }
+}
+
+/// writes stereo `frames` to a WAV file at `path`. samples are clamped to [-1.0, 1.0] for the
+/// integer formats.
+pub fn write_wav(
+    frames: &[StereoFrame],
+    bit_depth: BitDepth,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let spec = bit_depth.spec();
+    let mut writer = WavWriter::create(path.as_ref(), spec)?;
+    let max = ((1_i64 << (spec.bits_per_sample - 1)) - 1) as f32;
+
+    for sample in frames.iter().flat_map(|frame| [frame.left, frame.right]) {
+        match bit_depth {
+            BitDepth::Int16 => writer.write_sample((sample.clamp(-1.0, 1.0) * max) as i16)?,
+            BitDepth::Int24 => writer.write_sample((sample.clamp(-1.0, 1.0) * max) as i32)?,
+            BitDepth::Float32 => writer.write_sample(sample)?,
+        }
+    }
+
+    writer.finalize()?;
+
+    Ok(())
+}
+
+/// renders `sequences` with `synth` and writes the result to a WAV file at `path`. `synth` is
+/// left in whatever state the render leaves it in, so pass a copy of the live synth.
+pub fn render_to_wav(
+    synth: &mut Synth,
+    sequences: &[Sequence],
+    settings: &RenderSettings,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let frames = render(synth, sequences, settings)?;
+    write_wav(&frames, settings.bit_depth, path.as_ref())?;
+
+    info!(
+        "rendered {:.1} seconds of audio to {}",
+        frames.len() as f32 / sample_rate() as f32,
+        path.as_ref().display()
+    );
+
+    Ok(())
+}
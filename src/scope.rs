@@ -0,0 +1,216 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// how many samples the tap holds, a few times what the GUI reads so the audio thread doesn't
+/// catch up with a read in progress.
+pub const SCOPE_RING_SIZE: usize = 8192;
+/// samples shown in one scope frame, about 21 ms at 48 kHz.
+pub const SCOPE_WINDOW: usize = 1024;
+/// samples in each spectrum, must be a power of two.
+pub const FFT_SIZE: usize = 2048;
+/// the quietest a spectrum bin reports, in dB.
+const FLOOR_DB: f32 = -120.0;
+
+/// makes the two ends of a scope tap. the audio thread writes over the oldest samples and never
+/// waits, so however long the GUI looks away it always comes back to the newest.
+pub fn scope_tap() -> (ScopeTx, Scope) {
+    let ring = Arc::new(Ring {
+        samples: (0..SCOPE_RING_SIZE).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+    });
+
+    (ScopeTx { ring: ring.clone() }, Scope::new(ring))
+}
+
+#[derive(Debug)]
+struct Ring {
+    /// `f32` bits, sample `i` goes at `i % SCOPE_RING_SIZE`.
+    samples: Box<[AtomicU32]>,
+    /// how many samples have ever been written.
+    written: AtomicUsize,
+}
+
+/// the writing end of a scope tap, for the audio thread.
+#[derive(Debug)]
+pub struct ScopeTx {
+    ring: Arc<Ring>,
+}
+
+impl ScopeTx {
+    pub fn extend(&mut self, samples: impl IntoIterator<Item = f32>) {
+        let mut written = self.ring.written.load(Ordering::Relaxed);
+
+        for sample in samples {
+            self.ring.samples[written % SCOPE_RING_SIZE].store(sample.to_bits(), Ordering::Relaxed);
+            written = written.wrapping_add(1);
+        }
+
+        self.ring.written.store(written, Ordering::Release);
+    }
+}
+
+/// the reading end of a scope tap. keeps the most recent samples for the scope and spectrum.
+#[derive(Debug)]
+pub struct Scope {
+    ring: Arc<Ring>,
+    /// `Ring::written` when the GUI last read.
+    read: usize,
+    /// a circular buffer of the last `FFT_SIZE` samples, `pos` is the oldest.
+    history: Vec<f32>,
+    pos: usize,
+}
+
+impl Scope {
+    fn new(ring: Arc<Ring>) -> Self {
+        Self {
+            ring,
+            read: 0,
+            history: vec![0.0; FFT_SIZE.max(SCOPE_WINDOW * 2)],
+            pos: 0,
+        }
+    }
+
+    /// copies in what was written since the last read, only the newest if that's more than
+    /// `history` holds.
+    fn drain(&mut self) {
+        let written = self.ring.written.load(Ordering::Acquire);
+        let new = written.wrapping_sub(self.read).min(self.history.len());
+
+        for i in written.wrapping_sub(new)..written {
+            let sample = self.ring.samples[i % SCOPE_RING_SIZE].load(Ordering::Relaxed);
+            self.history[self.pos] = f32::from_bits(sample);
+            self.pos = (self.pos + 1) % self.history.len();
+        }
+
+        self.read = written;
+    }
+
+    /// the last `n` samples, oldest first.
+    fn latest(&self, n: usize) -> Vec<f32> {
+        let len = self.history.len();
+
+        (len - n..len)
+            .map(|i| self.history[(self.pos + i) % len])
+            .collect()
+    }
+
+    /// the last `SCOPE_WINDOW` samples resampled to exactly `width` points. the frame starts on a
+    /// rising zero crossing when there is one, so a steady note stands still on screen.
+    pub fn frame(&mut self, width: usize) -> Vec<f32> {
+        self.drain();
+
+        let samples = self.latest(SCOPE_WINDOW * 2);
+        let start = (1..=SCOPE_WINDOW)
+            .rev()
+            .find(|i| samples[i - 1] < 0.0 && samples[*i] >= 0.0)
+            .unwrap_or(SCOPE_WINDOW);
+
+        (0..width)
+            .map(|i| samples[start + i * SCOPE_WINDOW / width])
+            .collect()
+    }
+
+    /// the magnitude spectrum of the last `FFT_SIZE` samples in dB, from 0 Hz up to half the sample
+    /// rate, in exactly `n_bins` bins. with fewer bins than the FFT's `FFT_SIZE / 2` each keeps the
+    /// loudest of its group, with more the FFT's bins are repeated.
+    pub fn spectrum(&mut self, n_bins: usize) -> Vec<f32> {
+        self.drain();
+
+        let mut re: Vec<f32> = self
+            .latest(FFT_SIZE)
+            .into_iter()
+            .enumerate()
+            .map(|(i, sample)| sample * hann(i, FFT_SIZE))
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+
+        // a full scale sine through the hann window peaks at a quarter of the fft size.
+        let scale = 4.0 / FFT_SIZE as f32;
+        let magnitudes: Vec<f32> = re[..FFT_SIZE / 2]
+            .iter()
+            .zip(im.iter())
+            .map(|(re, im)| (20.0 * ((re * re + im * im).sqrt() * scale).log10()).max(FLOOR_DB))
+            .collect();
+        let len = magnitudes.len();
+
+        (0..n_bins)
+            .map(|i| {
+                let from = i * len / n_bins;
+                let to = ((i + 1) * len / n_bins).max(from + 1);
+
+                magnitudes[from..to]
+                    .iter()
+                    .copied()
+                    .fold(FLOOR_DB, f32::max)
+            })
+            .collect()
+    }
+}
+
+fn hann(i: usize, len: usize) -> f32 {
+    0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos()
+}
+
+/// an in place radix-2 FFT, `re` and `im` must be the same power of two long.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let bits = n.trailing_zeros();
+
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_scope_shows_the_newest_samples_after_being_away() {
+        let (mut tx, mut scope) = scope_tap();
+        let n = SCOPE_RING_SIZE * 3 + 5;
+
+        tx.extend((0..n).map(|i| i as f32));
+        scope.drain();
+
+        assert_eq!(scope.latest(3), [n - 3, n - 2, n - 1].map(|i| i as f32));
+
+        tx.extend([-1.0]);
+        scope.drain();
+
+        assert_eq!(scope.latest(2), [(n - 1) as f32, -1.0]);
+    }
+}
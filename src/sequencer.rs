@@ -1,7 +1,15 @@
 use crate::{
+    commands::{SynthCmd, SynthCtrl},
+    config::TransportCCs,
+    midi_clock::{step_at, ClockMessage, ClockSource, MidiClock, PPQN},
+    midi_learn::MidiLearn,
+    midi_ports::MidiOutputs,
+    patch_bank::PatchBank,
     pygame_coms::SynthEngineType,
-    synth_engines::{Synth, SynthEngine},
-    HashSet, MidiControlled,
+    session::Session,
+    synth_engines::pedals::{PEDAL_DOWN, SOSTENUTO_CC, SUSTAIN_CC},
+    sysex::Dump,
+    HashMap, HashSet, MidiControlled,
 };
 use log::*;
 use midi_control::{ControlEvent, KeyEvent, MidiMessage, MidiNote};
@@ -14,6 +22,7 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
+    thread::sleep,
     time::{Duration, Instant},
     u16,
 };
@@ -40,6 +49,29 @@ pub enum StepCmd {
     },
 }
 
+impl StepCmd {
+    /// the raw MIDI message for this command on `channel`, 0 to 15.
+    pub fn to_midi(&self, channel: u8) -> [u8; 3] {
+        let channel = channel & 0x0f;
+
+        match *self {
+            Self::Play { note, vel } => [0x90 | channel, note, vel],
+            Self::Stop { note } => [0x80 | channel, note, 0],
+            Self::CC { code, value } => [0xB0 | channel, code, value],
+        }
+    }
+}
+
+/// an external MIDI output that steps are sent to instead of the internal engines.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MidiOutTarget {
+    /// matched against the output names, ignoring case. the first output containing it is used.
+    pub port: String,
+    /// the MIDI channel to send on, 0 to 15.
+    pub channel: u8,
+}
+
 #[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Step {
@@ -52,6 +84,9 @@ pub struct Step {
 pub struct Sequence {
     pub human_name: Option<String>,
     pub steps: Vec<Step>,
+    /// sends every step of this sequence to an external output, whatever channel it was recorded on.
+    #[serde(default)]
+    pub midi_out: Option<MidiOutTarget>,
 }
 
 impl Default for Sequence {
@@ -64,6 +99,7 @@ impl Default for Sequence {
         Self {
             human_name: None,
             steps,
+            midi_out: None,
         }
     }
 }
@@ -73,6 +109,8 @@ impl Default for Sequence {
 pub struct StepperState {
     pub recording: bool,
     pub playing: AtomicBool,
+    /// carry on from the play head the next time the transport starts, instead of the first step.
+    pub resume: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -141,18 +179,23 @@ impl IndexMut<SequenceIndex> for Vec<Sequence> {
 #[derive(Debug)]
 pub struct SequencerIntake {
     sequences: Vec<Sequence>,
-    #[cfg(feature = "pyo3")]
-    pub synth: Synth,
+    pub synth: SynthCtrl,
     // sequence_i: usize,
     pub rec_head: SequenceIndex,
     pub play_head: SequenceIndex,
     pub state: StepperState,
     pub bpm: u16,
+    pub transport: TransportCCs,
+    /// external outputs for step channels, used by sequences without their own `midi_out`.
+    pub midi_out: HashMap<u8, MidiOutTarget>,
+    pub clock: MidiClock,
+    pub learn: MidiLearn,
+    /// the patches Program Change recalls.
+    pub patches: PatchBank,
 }
 
 impl SequencerIntake {
-    #[cfg(feature = "pyo3")]
-    pub fn new(synth: Synth) -> Self {
+    pub fn new(synth: SynthCtrl) -> Self {
         Self {
             sequences: vec![
                 Sequence::default(),
@@ -165,26 +208,15 @@ impl SequencerIntake {
             play_head: SequenceIndex::default(),
             state: StepperState::default(),
             bpm: 120,
+            transport: TransportCCs::default(),
+            midi_out: HashMap::default(),
+            clock: MidiClock::default(),
+            learn: MidiLearn::default(),
+            patches: PatchBank::default(),
             synth,
         }
     }
 
-    #[cfg(not(feature = "pyo3"))]
-    pub fn new() -> Self {
-        Self {
-            sequences: vec![
-                Sequence::default(),
-                Sequence::default(),
-                Sequence::default(),
-                Sequence::default(),
-            ],
-            rec_head: SequenceIndex::default(),
-            play_head: SequenceIndex::default(),
-            state: StepperState::default(),
-            bpm: 120,
-        }
-    }
-
     pub fn get_step(&self, play: bool) -> Step {
         let i = if play {
             self.play_head.clone()
@@ -294,11 +326,119 @@ impl SequencerIntake {
             .collect();
     }
 
+    /// sends every step of sequence `at` to `target`, or back to the internal engines with `None`.
+    pub fn set_sequence_midi_out(&mut self, at: usize, target: Option<MidiOutTarget>) {
+        if let Some(sequence) = self.sequences.get_mut(at) {
+            sequence.midi_out = target;
+        }
+    }
+
+    /// sends step channel `ch` to `target`, or back to the internal engines with `None`.
+    pub fn set_channel_midi_out(&mut self, ch: u8, target: Option<MidiOutTarget>) {
+        if let Some(target) = target {
+            self.midi_out.insert(ch, target);
+        } else {
+            self.midi_out.remove(&ch);
+        }
+    }
+
+    /// where the playing sequence sends step channel `ch`, `None` means the internal engines.
+    pub fn midi_out_target(&self, ch: u8) -> Option<MidiOutTarget> {
+        self.sequences[self.play_head.sequence]
+            .midi_out
+            .clone()
+            .or_else(|| self.midi_out.get(&ch).cloned())
+    }
+
+    /// handles MIDI clock and transport that arrived at `stamp` microseconds. ignored unless the
+    /// clock source is external. returns true if the GUI should redraw.
+    pub fn clock_input(&mut self, message: ClockMessage, stamp: u64) -> bool {
+        if self.clock.source != ClockSource::External {
+            return false;
+        }
+
+        let playing = self.state.playing.load(Ordering::Relaxed);
+
+        match message {
+            ClockMessage::Tick => return self.clock.tick(stamp, playing),
+            ClockMessage::Start => {
+                self.clock.locate(0);
+                self.state.resume = false;
+
+                if playing {
+                    // already running, so wrap round to the first step straight away.
+                    self.play_head.step = self.sequences[self.play_head.sequence].steps.len() - 1;
+                    self.clock.cue_step();
+                } else {
+                    self.play_head.step = 0;
+                }
+
+                self.state.recording = false;
+                self.state.playing.store(true, Ordering::Relaxed);
+            }
+            ClockMessage::Continue => {
+                self.state.resume = true;
+                self.state.recording = false;
+                self.state.playing.store(true, Ordering::Relaxed);
+            }
+            ClockMessage::Stop => self.state.playing.store(false, Ordering::Relaxed),
+            // only meaningful while stopped.
+            ClockMessage::SongPosition(_) if playing => return false,
+            ClockMessage::SongPosition(pos) => {
+                let n_steps = self.sequences[self.play_head.sequence].steps.len();
+                self.clock.locate(pos);
+                self.play_head.step = step_at(pos, n_steps);
+                self.state.resume = true;
+            }
+        }
+
+        true
+    }
+
     pub fn get_sequence(&self) -> Sequence {
         // self.sequences[i].clone()
         self.sequences[self.rec_head.sequence].clone()
     }
 
+    pub fn get_sequences(&self) -> Vec<Sequence> {
+        self.sequences.clone()
+    }
+
+    /// replaces every sequence, both heads go back to the start of the first one.
+    pub fn set_sequences(&mut self, sequences: Vec<Sequence>) {
+        if sequences.is_empty() {
+            warn!("refusing to replace the sequences with nothing");
+            return;
+        }
+
+        self.sequences = sequences;
+        self.rec_head = SequenceIndex::default();
+        self.play_head = SequenceIndex::default();
+    }
+
+    /// loads `session`'s preset and tempo, and its sequences if it has any.
+    pub fn load_session(&mut self, session: Session) {
+        self.synth
+            .send(SynthCmd::LoadPreset(Box::new(session.preset)));
+        self.bpm = session.bpm;
+
+        if !session.sequences.is_empty() {
+            self.set_sequences(session.sequences);
+        }
+    }
+
+    /// loads a dump that came in as SysEx.
+    pub fn load_dump(&mut self, dump: Dump) {
+        if dump.preset().wave_table.is_none() {
+            warn!("the dump has no wave table patch, it's from an older version. keeping ours");
+        }
+
+        match dump {
+            Dump::Preset(preset) => self.synth.send(SynthCmd::LoadPreset(preset)),
+            Dump::Session(session) => self.load_session(*session),
+        }
+    }
+
     pub fn set_rec_head_seq(&mut self, seq: i64) {
         self.rec_head.sequence = (seq % self.sequences.len() as i64) as usize;
     }
@@ -314,32 +454,63 @@ impl SequencerIntake {
 
 impl MidiControlled for SequencerIntake {
     fn midi_input(&mut self, message: &MidiMessage) {
-        #[cfg(feature = "pyo3")]
-        self.synth.midi_input(message);
+        if let MidiMessage::ControlChange(channel, ControlEvent { control, value }) = *message {
+            let channel = channel as u8;
+
+            // bank select only picks the bank the next Program Change recalls from.
+            if self.patches.bank_select(channel, control, value) {
+                return;
+            }
+
+            // learned CCs only do what they were bound to.
+            if self.learn.bind(channel, control) {
+                return;
+            } else if let Some(binding) = self.learn.binding(channel, control) {
+                self.synth.send(SynthCmd::SetTarget {
+                    target: binding.target.clone(),
+                    value: binding.scale(value),
+                });
+                return;
+            }
+        }
+
+        if let MidiMessage::ProgramChange(channel, program) = *message {
+            if let Some(patch) = self.patches.program_change(channel as u8, program) {
+                info!("recalling patch {:?}", patch.name);
+                self.synth
+                    .send(SynthCmd::LoadPreset(Box::new(patch.preset.clone())));
+            }
+
+            return;
+        }
+
+        self.synth.send(SynthCmd::Midi(message.clone()));
 
         if let MidiMessage::ControlChange(_channel, ControlEvent { control, value: _ }) = message {
-            match control {
-                115 => {
+            let transport = self.transport;
+
+            match *control {
+                cc if cc == transport.prev_step => {
                     self.rec_head.step = if self.rec_head.step > 0 {
                         self.rec_head.step - 1
                     } else {
                         self.sequences[self.rec_head.sequence].steps.len() - 1
                     };
                 }
-                116 => {
+                cc if cc == transport.next_step => {
                     self.rec_head.step += 1;
                     self.rec_head.step %= self.sequences[self.rec_head.sequence].steps.len();
                 }
-                117 => {
+                cc if cc == transport.stop => {
                     self.state.playing.store(false, Ordering::Relaxed);
                     self.state.recording = false;
                 }
-                118 => {
+                cc if cc == transport.play => {
                     self.state.playing.store(true, Ordering::Relaxed);
                     self.state.recording = false;
                     info!("setting playing to true");
                 }
-                119 => {
+                cc if cc == transport.record => {
                     self.state.playing.store(false, Ordering::Relaxed);
                     self.state.recording = true;
                 }
@@ -375,6 +546,19 @@ impl MidiControlled for SequencerIntake {
                     false,
                 )
             }
+            // pedal downs play as the step starts and pedal ups as it ends.
+            MidiMessage::ControlChange(channel, ControlEvent { control, value })
+                if control == SUSTAIN_CC || control == SOSTENUTO_CC =>
+            {
+                (
+                    channel as u8,
+                    StepCmd::CC {
+                        code: control,
+                        value,
+                    },
+                    value >= PEDAL_DOWN,
+                )
+            }
             MidiMessage::PitchBend(_channel, _lsb, _msb) => return,
             _ => {
                 return;
@@ -429,47 +613,130 @@ impl MidiControlled for SequencerIntake {
     }
 }
 
-#[cfg(feature = "pyo3")]
-pub fn play_sequence(seq: Arc<Mutex<SequencerIntake>>) {
-    let mut beat_time = Duration::from_secs_f64(60.0 / seq.lock().unwrap().bpm as f64);
-    // let mut last_on_exit = HashSet::default();
-    let synth_types: Vec<SynthEngineType> = SynthEngineType::iter().collect();
-    let mut playing: HashSet<(u8, u8)> = HashSet::default();
-
-    let mut send_midi = |synth: &mut Synth, midi_s: MidiMessages| {
-        for midi in midi_s {
-            let instrument = if midi.0 == 0 {
-                synth.get_engine()
-            } else if let Some(synth_type) = synth_types.get((midi.0 - 1) as usize) {
-                synth.engines.index_mut(*synth_type as usize)
-            } else {
-                continue;
-            };
+/// turns a recorded step command into a synth command. channel 0 plays on the selected engine,
+/// channel n on the nth engine. returns `None` for channels with no engine.
+pub fn step_cmd(ch: u8, cmd: &StepCmd) -> Option<SynthCmd> {
+    let engine = if ch == 0 {
+        None
+    } else {
+        Some(SynthEngineType::iter().nth((ch - 1) as usize)?)
+    };
 
-            match midi.1 {
-                StepCmd::Play { note, vel } => {
-                    playing.insert((midi.0, note));
+    match *cmd {
+        StepCmd::Play { note, vel } => Some(SynthCmd::Play {
+            engine,
+            channel: ch,
+            note,
+            vel,
+        }),
+        StepCmd::Stop { note } => Some(SynthCmd::Stop {
+            engine,
+            channel: ch,
+            note,
+        }),
+        StepCmd::CC { code, value } => Some(SynthCmd::CC {
+            engine,
+            code,
+            value,
+        }),
+    }
+}
 
-                    instrument.play(note, vel)
-                }
-                StepCmd::Stop { note } => {
-                    playing.remove(&(midi.0, note));
+/// plays the sequences whenever the transport is running, until `exit` is set. stop the transport
+/// before setting `exit` so the playing notes get their note off.
+pub fn run_sequencer(seq: Arc<Mutex<SequencerIntake>>, exit: Arc<AtomicBool>) {
+    let mut midi_out = MidiOutputs::default();
 
-                    instrument.stop(note)
-                }
-                StepCmd::CC { code: _, value: _ } => {}
-            }
+    while !exit.load(Ordering::Relaxed) {
+        if seq.lock().unwrap().state.playing.load(Ordering::Relaxed) {
+            play_sequence(seq.clone(), &mut midi_out);
+        } else {
+            sleep(Duration::from_millis(1));
         }
+    }
+
+    midi_out.close();
+}
+
+/// sends a step command to the internal engines, or out of `target` when there is one.
+fn send_step_cmd(
+    synth: &SynthCtrl,
+    midi_out: &mut MidiOutputs,
+    target: Option<&MidiOutTarget>,
+    ch: u8,
+    cmd: &StepCmd,
+) {
+    if let Some(target) = target {
+        midi_out.send(&target.port, &cmd.to_midi(target.channel));
+    } else if let Some(cmd) = step_cmd(ch, cmd) {
+        synth.send(cmd);
+    }
+}
+
+/// sends a clock or transport message to `port`, if there is one.
+fn send_clock(midi_out: &mut MidiOutputs, port: Option<&str>, message: ClockMessage) {
+    if let Some(port) = port {
+        midi_out.send(port, &message.to_midi());
+    }
+}
+
+/// plays from the first step, or from the play head if `state.resume` is set, until the transport
+/// stops. the clock source and output are read once, changes take effect on the next start.
+pub fn play_sequence(seq: Arc<Mutex<SequencerIntake>>, midi_out: &mut MidiOutputs) {
+    let (following, clock_out, start_step) = {
+        let mut seq = seq.lock().unwrap();
+
+        if !seq.state.resume {
+            seq.play_head.step = 0;
+        }
+
+        seq.state.resume = false;
+        let following = seq.clock.source == ClockSource::External;
+
+        (
+            following,
+            seq.clock.out.clone().filter(|_| !following),
+            seq.play_head.step,
+        )
     };
+    let clock_out = clock_out.as_deref();
+    let mut beat_time = Duration::from_secs_f64(60.0 / seq.lock().unwrap().bpm as f64);
+    // let mut last_on_exit = HashSet::default();
+    // remembers where each note went so its note off follows it.
+    let mut playing: HashSet<(u8, u8, Option<MidiOutTarget>)> = HashSet::default();
+    // pedals a step put down, lifted when the transport stops so nothing hangs.
+    let mut pedals: HashSet<(u8, u8, Option<MidiOutTarget>)> = HashSet::default();
+
+    let mut send_midi =
+        |seq: &SequencerIntake, midi_out: &mut MidiOutputs, midi_s: MidiMessages| {
+            for (ch, cmd) in midi_s {
+                let target = seq.midi_out_target(ch);
+
+                match cmd {
+                    StepCmd::Play { note, vel: _ } => playing.insert((ch, note, target.clone())),
+                    StepCmd::Stop { note } => playing.remove(&(ch, note, target.clone())),
+                    StepCmd::CC { code, value } if code == SUSTAIN_CC || code == SOSTENUTO_CC => {
+                        if value >= PEDAL_DOWN {
+                            pedals.insert((ch, code, target.clone()))
+                        } else {
+                            pedals.remove(&(ch, code, target.clone()))
+                        }
+                    }
+                    StepCmd::CC { code: _, value: _ } => false,
+                };
 
-    let mut play_step = |last_on_exit: MidiMessages| {
+                send_step_cmd(&seq.synth, midi_out, target.as_ref(), ch, &cmd);
+            }
+        };
+
+    let mut play_step = |midi_out: &mut MidiOutputs, last_on_exit: MidiMessages| {
         // info!("beat");
-        let mut seq = seq.lock().unwrap();
+        let seq = seq.lock().unwrap();
         // info!("after sequence lock");
         let step = seq.sequences[seq.play_head].clone();
-        send_midi(&mut seq.synth, last_on_exit);
+        send_midi(&seq, midi_out, last_on_exit);
 
-        send_midi(&mut seq.synth, step.on_enter);
+        send_midi(&seq, midi_out, step.on_enter);
         step.on_exit.clone()
     };
     let inc_step = || {
@@ -479,8 +746,18 @@ pub fn play_sequence(seq: Arc<Mutex<SequencerIntake>>) {
         seq.play_head.step %= seq.sequences[seq.play_head.sequence].steps.len();
     };
 
-    let mut last_on_exit = play_step(HashSet::default());
+    if start_step == 0 {
+        send_clock(midi_out, clock_out, ClockMessage::Start);
+    } else {
+        send_clock(midi_out, clock_out, ClockMessage::song_position(start_step));
+        send_clock(midi_out, clock_out, ClockMessage::Continue);
+    }
+
+    let mut last_on_exit = play_step(midi_out, HashSet::default());
     let mut last_play = Instant::now();
+    send_clock(midi_out, clock_out, ClockMessage::Tick);
+    // clock ticks sent this beat.
+    let mut ticks = 1;
 
     while seq
         .clone()
@@ -490,24 +767,47 @@ pub fn play_sequence(seq: Arc<Mutex<SequencerIntake>>) {
         .playing
         .load(Ordering::Relaxed)
     {
-        if last_play.elapsed() >= beat_time {
+        if following {
+            if seq.lock().unwrap().clock.take_step() {
+                inc_step();
+                last_on_exit = play_step(midi_out, last_on_exit);
+            }
+        } else if last_play.elapsed() >= beat_time {
             inc_step();
-            last_on_exit = play_step(last_on_exit);
+            last_on_exit = play_step(midi_out, last_on_exit);
 
             beat_time = Duration::from_secs_f64(60.0 / seq.lock().unwrap().bpm as f64);
             last_play = Instant::now();
+            send_clock(midi_out, clock_out, ClockMessage::Tick);
+            ticks = 1;
+        } else if clock_out.is_some()
+            && ticks < PPQN
+            && last_play.elapsed() >= beat_time * ticks / PPQN
+        {
+            send_clock(midi_out, clock_out, ClockMessage::Tick);
+            ticks += 1;
         }
     }
 
-    let mut seq = seq.lock().unwrap();
-    seq.play_head.step = 0;
-    playing.into_iter().for_each(|(ch, note)| {
-        let synth = &mut seq.synth;
-
-        if ch == 0 {
-            synth.get_engine().stop(note);
-        } else if let Some(synth_type) = synth_types.get((ch - 1) as usize) {
-            synth.engines.index_mut(*synth_type as usize).stop(note);
-        }
+    send_clock(midi_out, clock_out, ClockMessage::Stop);
+
+    let seq = seq.lock().unwrap();
+    playing.into_iter().for_each(|(ch, note, target)| {
+        send_step_cmd(
+            &seq.synth,
+            midi_out,
+            target.as_ref(),
+            ch,
+            &StepCmd::Stop { note },
+        );
+    });
+    pedals.into_iter().for_each(|(ch, code, target)| {
+        send_step_cmd(
+            &seq.synth,
+            midi_out,
+            target.as_ref(),
+            ch,
+            &StepCmd::CC { code, value: 0 },
+        );
     })
 }
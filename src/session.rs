@@ -0,0 +1,246 @@
+use crate::{
+    effects::{Effect, EffectType},
+    pygame_coms::{GuiParam, Knob, SynthEngineType},
+    sequencer::Sequence,
+    synth_engines::{
+        synth_common::velocity::Velocity, wave_table::WaveTablePatch, BendRange, Synth,
+        SynthEngine, SynthModule,
+    },
+    HashMap, KnobCtrl,
+};
+use anyhow::Result;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+use strum::IntoEnumIterator;
+
+/// the settings of one engine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnginePatch {
+    pub engine: SynthEngineType,
+    #[serde(default)]
+    pub pan: f32,
+    #[serde(default = "default_level")]
+    pub level: f32,
+    #[serde(default)]
+    pub mute: bool,
+    #[serde(default)]
+    pub knobs: HashMap<Knob, f32>,
+    #[serde(default)]
+    pub gui: HashMap<GuiParam, f32>,
+    /// `None` for engines without velocity settings.
+    #[serde(default)]
+    pub velocity: Option<Velocity>,
+    #[serde(default)]
+    pub bend: BendRange,
+}
+
+/// the settings of one effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectPatch {
+    pub effect: EffectType,
+    #[serde(default)]
+    pub params: HashMap<String, f32>,
+}
+
+/// the sound of the whole synth.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub engine: SynthEngineType,
+    pub effect: EffectType,
+    #[serde(default)]
+    pub effect_on: bool,
+    #[serde(default)]
+    pub engines: Vec<EnginePatch>,
+    #[serde(default)]
+    pub effects: Vec<EffectPatch>,
+    /// `None` in presets saved before the wave table engine was.
+    #[serde(default)]
+    pub wave_table: Option<WaveTablePatch>,
+}
+
+/// a preset plus the sequencer, everything needed to pick up where you left off. a file with only
+/// a `[preset]` table is a valid session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default = "default_bpm")]
+    pub bpm: u16,
+    pub preset: Preset,
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+}
+
+fn default_bpm() -> u16 {
+    120
+}
+
+fn default_level() -> f32 {
+    1.0
+}
+
+impl Session {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let session = toml::from_str(&fs::read_to_string(path.as_ref())?)?;
+        info!("loaded session from {}", path.as_ref().display());
+
+        Ok(session)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path.as_ref(), toml::to_string_pretty(self)?)?;
+        info!("saved session to {}", path.as_ref().display());
+
+        Ok(())
+    }
+}
+
+impl Synth {
+    pub fn preset(&self) -> Preset {
+        Preset {
+            engine: self.engine_type,
+            effect: self.effect_type,
+            effect_on: self.effect_power,
+            engines: SynthEngineType::iter()
+                .map(|engine| EnginePatch {
+                    engine,
+                    pan: self.mixer[engine as usize].pan,
+                    level: self.mixer[engine as usize].level,
+                    mute: self.mixer[engine as usize].mute,
+                    knobs: self.engines[engine as usize].get_params(),
+                    gui: self.engines[engine as usize].get_gui_params(),
+                    velocity: self.engines[engine as usize].velocity(),
+                    bend: self.bend_ranges[engine as usize],
+                })
+                .collect(),
+            effects: EffectType::iter()
+                .map(|effect| EffectPatch {
+                    effect,
+                    params: self.effects[effect as usize].get_params(),
+                })
+                .collect(),
+            wave_table: match &self.engines[SynthEngineType::WaveTable as usize] {
+                SynthModule::WaveTable(wt) => Some(wt.patch()),
+                _ => None,
+            },
+        }
+    }
+
+    /// applies `preset` through the same knob and gui setters the controls use.
+    pub fn load_preset(&mut self, preset: &Preset) {
+        self.set_engine(preset.engine);
+        self.set_effect(preset.effect);
+        self.effect_power = preset.effect_on;
+
+        for patch in preset.engines.iter() {
+            let i = patch.engine as usize;
+            self.set_pan(patch.engine, patch.pan);
+            self.set_level(patch.engine, patch.level);
+            self.set_mute(patch.engine, patch.mute);
+            self.set_bend_range(patch.engine, patch.bend);
+
+            for (param, value) in patch.gui.iter() {
+                self.engines[i].gui_param(*param, *value);
+            }
+
+            for (knob, value) in patch.knobs.iter() {
+                self.engines[i].knob(*knob, *value);
+            }
+
+            if let Some(velocity) = patch.velocity {
+                self.set_velocity(patch.engine, velocity);
+            }
+        }
+
+        for patch in preset.effects.iter() {
+            for (param, value) in patch.params.iter() {
+                self.effects[patch.effect as usize].set_param(param, *value);
+            }
+        }
+
+        if let (Some(patch), SynthModule::WaveTable(wt)) = (
+            &preset.wave_table,
+            &mut self.engines[SynthEngineType::WaveTable as usize],
+        ) {
+            wt.load_patch(patch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pygame_coms::WTSynthParam,
+        set_sample_rate,
+        synth_engines::{organ::organ::Organ, wave_table::AftertouchMod},
+    };
+    use wavetable_synth::common::{ModMatrixDest, ModMatrixItem, ModMatrixSrc, OscParam};
+
+    fn organ(synth: &Synth) -> &Organ {
+        match &synth.engines[SynthEngineType::B3Organ as usize] {
+            SynthModule::B3Organ(organ) => organ,
+            _ => unreachable!("the organ isn't where the organ goes"),
+        }
+    }
+
+    #[test]
+    fn organ_round_trip() {
+        set_sample_rate(48_000);
+        let mut synth = Synth::new();
+        synth.engines[SynthEngineType::B3Organ as usize].gui_param(GuiParam::E, 0.7);
+        synth.engines[SynthEngineType::B3Organ as usize].knob(Knob::Two, 0.25);
+
+        let preset = synth.preset();
+        let mut loaded = Synth::new();
+        loaded.load_preset(&preset);
+
+        assert_eq!(loaded.preset(), preset);
+
+        let (saved, loaded) = (organ(&synth), organ(&loaded));
+        assert_eq!(loaded.leslie, saved.leslie);
+        assert_eq!(loaded.speaker_speed, saved.speaker_speed);
+        assert_eq!(loaded.lfo.volume, saved.lfo.volume);
+    }
+
+    #[test]
+    fn wave_table_round_trip() {
+        set_sample_rate(48_000);
+        let mut synth = Synth::new();
+        let SynthModule::WaveTable(wt) = &mut synth.engines[SynthEngineType::WaveTable as usize]
+        else {
+            unreachable!("the wave table engine isn't where it goes");
+        };
+        wt.set_param(WTSynthParam::OscVol { n: 1, to: 0.3 });
+        wt.set_param(WTSynthParam::OscOn { n: 2, on: true });
+        wt.set_param(WTSynthParam::LowPassCutoff {
+            n: 0,
+            cutoff: 1_200.0,
+        });
+        wt.synth.mod_matrix[0] = Some(ModMatrixItem {
+            src: ModMatrixSrc::Env(1),
+            dest: ModMatrixDest::Osc {
+                osc: 0,
+                param: OscParam::Tune,
+            },
+            amt: 0.5,
+            bipolar: true,
+        });
+        wt.aftertouch_mods.push(AftertouchMod {
+            dest: ModMatrixDest::Osc {
+                osc: 1,
+                param: OscParam::Level,
+            },
+            amt: 0.25,
+            bipolar: false,
+        });
+
+        // through TOML, the way sessions and SysEx dumps carry it.
+        let preset: Preset = toml::from_str(&toml::to_string(&synth.preset()).unwrap()).unwrap();
+        assert_eq!(preset, synth.preset());
+
+        let mut loaded = Synth::new();
+        loaded.load_preset(&preset);
+
+        assert_eq!(loaded.preset(), preset);
+    }
+}
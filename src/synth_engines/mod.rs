@@ -1,51 +1,86 @@
 use crate::{
+    config::Config,
     effects::{Effect, EffectType, EffectsModule},
+    master_bus::MasterBus,
+    meter::Meters,
     pygame_coms::{GuiParam, Knob, SynthEngineType},
-    HashMap, KnobCtrl, MidiControlled, SampleGen,
+    HashMap, KnobCtrl, MidiControlled, SampleGen, StereoFrame,
 };
 use enum_dispatch::enum_dispatch;
 use log::*;
 use midi_control::MidiNote;
-use midi_control::{ControlEvent, KeyEvent, MidiMessage};
+use midi_control::{Channel, ControlEvent, KeyEvent, MidiMessage};
+use mpe::Mpe;
 use organ::organ::Organ;
+use pedals::{Pedals, PEDAL_DOWN, SOSTENUTO_CC, SUSTAIN_CC};
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{fmt::Debug, ops::IndexMut};
 use strum::IntoEnumIterator;
-use synth_common::lfo::LFO;
+use synth_common::{lfo::LFO, velocity::Velocity};
 use wavetable_synth::MidiControlled as _;
 // use synth_common::lfo::LFO;
 use wave_table::WaveTableEngine;
 use wurlitzer::WurlitzerEngine;
 
+pub mod mpe;
 pub mod organ;
+pub mod pedals;
 pub mod synth;
 pub mod synth_common;
 pub mod wave_table;
 pub mod wurlitzer;
 
+/// how many MIDI channels there are to route.
+pub const MIDI_CHANNELS: usize = 16;
+
 #[enum_dispatch]
 pub trait SynthEngine: Debug + SampleGen + KnobCtrl + Send + Clone {
     fn name(&self) -> String;
 
     fn play(&mut self, note: MidiNote, velocity: u8);
     fn stop(&mut self, note: MidiNote);
-    fn bend(&mut self, amount: f32);
+    /// bends every playing note `semitones` up, or down if negative.
+    fn bend(&mut self, semitones: f32);
     fn unbend(&mut self) {
         self.bend(0.0);
     }
+    /// channel aftertouch, from 0.0 to 1.0.
+    fn aftertouch(&mut self, _pressure: f32) {}
+    /// aftertouch on one note, from 0.0 to 1.0. engines without per note expression treat it as
+    /// channel aftertouch. this is also MPE's per note pressure.
+    fn poly_aftertouch(&mut self, _note: MidiNote, pressure: f32) {
+        self.aftertouch(pressure);
+    }
+    /// bends only the voice playing `note` `semitones`, for MPE. engines without per note pitch
+    /// bend every voice.
+    fn note_bend(&mut self, _note: MidiNote, semitones: f32) {
+        self.bend(semitones);
+    }
+    /// the MPE timbre (CC74) of the voice playing `note`, from 0.0 to 1.0.
+    fn note_timbre(&mut self, _note: MidiNote, _timbre: f32) {}
     fn volume_swell(&mut self, amount: f32) -> bool;
+    /// false once every voice has gone quiet, so the mixer can skip the engine.
+    fn is_active(&self) -> bool {
+        true
+    }
     fn get_params(&self) -> HashMap<Knob, f32>;
     fn get_gui_params(&self) -> HashMap<GuiParam, f32>;
-    // TODO: impl sustain_peddal
-    // fn sustain_peddal(&mut self);
+    /// how the engine responds to velocity, `None` if it isn't adjustable.
+    fn velocity(&self) -> Option<Velocity> {
+        None
+    }
+    fn set_velocity(&mut self, _velocity: Velocity) -> bool {
+        false
+    }
 }
 
 #[cfg_attr(
     feature = "pyo3",
     pyclass(module = "stepper_synth_backend", get_all, eq)
 )]
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Param {
     Knob(Knob),
     Gui(GuiParam),
@@ -87,11 +122,94 @@ impl From<SynthEngineType> for SynthModule {
     }
 }
 
+/// one engine's strip on the mixer.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MixerChannel {
+    pub level: f32,
+    pub mute: bool,
+    /// -1.0 is hard left and 1.0 hard right.
+    pub pan: f32,
+}
+
+impl Default for MixerChannel {
+    fn default() -> Self {
+        Self {
+            level: 1.0,
+            mute: false,
+            pan: 0.0,
+        }
+    }
+}
+
+impl MixerChannel {
+    pub fn audible(&self) -> bool {
+        !self.mute && self.level > 0.0
+    }
+}
+
+/// the pitch wheel's value at rest.
+pub const BEND_CENTER: u16 = 0x2000;
+/// the widest bend range that can be set, in semitones.
+pub const MAX_BEND_RANGE: f32 = 48.0;
+
+/// the 14 bit value of a pitch bend message, from its two 7 bit data bytes.
+pub fn pitch_bend_value(lsb: u8, msb: u8) -> u16 {
+    (lsb as u16 & 0x7f) | ((msb as u16 & 0x7f) << 7)
+}
+
+/// how far the pitch wheel bends an engine, in semitones each way.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BendRange {
+    pub up: f32,
+    pub down: f32,
+}
+
+impl Default for BendRange {
+    fn default() -> Self {
+        Self { up: 3.0, down: 3.0 }
+    }
+}
+
+impl BendRange {
+    /// the bend in semitones for a 14 bit pitch bend `value`.
+    pub fn semitones(&self, value: u16) -> f32 {
+        let value = value.min(0x3fff) as f32 - BEND_CENTER as f32;
+
+        if value >= 0.0 {
+            value / (0x3fff - BEND_CENTER) as f32 * self.up
+        } else {
+            value / BEND_CENTER as f32 * self.down
+        }
+    }
+}
+
+/// the parts of a synth that hold sound, built off the audio thread to replace ones that blew up.
+#[derive(Debug)]
+pub struct SynthParts {
+    engines: Box<[SynthModule]>,
+    effects: Box<[EffectsModule]>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Synth {
     pub lfo: LFO,
     pub engines: Box<[SynthModule]>,
     pub engine_type: SynthEngineType,
+    /// every engine's level, mute and pan, indexed like `engines`.
+    pub mixer: Box<[MixerChannel]>,
+    /// runs after the effect, keeps the output in range.
+    pub master: MasterBus,
+    pub meters: Meters,
+    /// the engine the scope watches, after its fader. `None` watches the output.
+    pub scope_source: Option<SynthEngineType>,
     pub effect_power: bool,
     // pub effect: EffectsModules,
     pub effects: Box<[EffectsModule]>,
@@ -101,6 +219,25 @@ pub struct Synth {
     pub lfo_routed: bool,
     // pub stepper_state: StepperState,
     pub target_effects: bool,
+    /// the CCs for knobs one through eight.
+    pub knob_ccs: [u8; 8],
+    /// the engine each MIDI channel plays, indexed from 0. `None` plays the current engine.
+    pub midi_routes: [Option<SynthEngineType>; MIDI_CHANNELS],
+    /// each engine's sustain and sostenuto pedals, indexed like `engines`.
+    pub pedals: Box<[Pedals]>,
+    /// the engine each held note was played on, by channel and note, so its note off goes there
+    /// even if the current engine or the channel's route changes while it's down.
+    held: [[Option<SynthEngineType>; 128]; MIDI_CHANNELS],
+    /// how far the pitch wheel bends each engine, indexed like `engines`.
+    pub bend_ranges: Box<[BendRange]>,
+    pub mpe: Mpe,
+    /// scratch space the mono engines render into before they are panned.
+    mono_buf: Vec<f32>,
+    /// engines that skip the effect are summed here while the rest go through it.
+    dry_buf: Vec<StereoFrame>,
+    /// the last buffer from `scope_source`.
+    scope_buf: Vec<f32>,
+    scope_sample: f32,
 }
 
 impl Synth {
@@ -111,7 +248,11 @@ impl Synth {
         let effects = EffectType::iter()
             .map(|effect_type| effect_type.into())
             .collect();
+        let mixer = SynthEngineType::iter()
+            .map(|_| MixerChannel::default())
+            .collect();
         let engine_type = SynthEngineType::B3Organ;
+        let n_engines = SynthEngineType::iter().count();
         // let engine_type = SynthEngineType::WaveTable;
 
         Self {
@@ -123,11 +264,25 @@ impl Synth {
             lfo_target: None,
             engine_type,
             engines,
+            mixer,
+            master: MasterBus::new(),
+            meters: Meters::new(n_engines),
+            scope_source: None,
             // engine: Box::new(Organ::new()),
             // engine: SynthEngines::new(),
             lfo_routed: false,
             // stepper_state: StepperState::default(),
             target_effects: false,
+            knob_ccs: Config::default().knob_ccs,
+            midi_routes: [None; MIDI_CHANNELS],
+            pedals: vec![Pedals::default(); n_engines].into(),
+            held: [[None; 128]; MIDI_CHANNELS],
+            bend_ranges: vec![BendRange::default(); n_engines].into(),
+            mpe: Mpe::default(),
+            mono_buf: Vec::new(),
+            dry_buf: Vec::new(),
+            scope_buf: Vec::new(),
+            scope_sample: 0.0,
         }
     }
 
@@ -175,12 +330,215 @@ impl Synth {
         true
     }
 
+    /// a copy of the synth that shares no state with this one, safe to render on another thread.
+    pub fn detached_clone(&self) -> Self {
+        let mut synth = self.clone();
+
+        for engine in synth.engines.iter_mut() {
+            if let SynthModule::WaveTable(wt) = engine {
+                wt.detach();
+            }
+        }
+
+        synth
+    }
+
+    pub fn set_pan(&mut self, engine: SynthEngineType, pan: f32) -> bool {
+        self.mixer[engine as usize].pan = pan.clamp(-1.0, 1.0);
+
+        true
+    }
+
+    pub fn set_level(&mut self, engine: SynthEngineType, level: f32) -> bool {
+        self.mixer[engine as usize].level = level.clamp(0.0, 1.0);
+
+        true
+    }
+
+    pub fn set_mute(&mut self, engine: SynthEngineType, mute: bool) -> bool {
+        self.mixer[engine as usize].mute = mute;
+
+        true
+    }
+
+    pub fn set_velocity(&mut self, engine: SynthEngineType, velocity: Velocity) -> bool {
+        self.engines[engine as usize].set_velocity(velocity)
+    }
+
+    pub fn set_bend_range(&mut self, engine: SynthEngineType, range: BendRange) -> bool {
+        self.bend_ranges[engine as usize] = BendRange {
+            up: range.up.clamp(0.0, MAX_BEND_RANGE),
+            down: range.down.clamp(0.0, MAX_BEND_RANGE),
+        };
+
+        true
+    }
+
+    /// sends MIDI `channel`, from 0, to `engine`, or to the current engine if `None`.
+    pub fn set_midi_route(&mut self, channel: u8, engine: Option<SynthEngineType>) -> bool {
+        let Some(route) = self.midi_routes.get_mut(channel as usize) else {
+            warn!("there is no MIDI channel {channel} to route");
+            return false;
+        };
+
+        *route = engine;
+
+        true
+    }
+
+    /// the engine MIDI on `channel` plays.
+    pub fn channel_engine(&mut self, channel: Channel) -> &mut SynthModule {
+        let engine = self.midi_routes[channel as usize];
+
+        self.engine_for(engine)
+    }
+
+    /// plays `note` from `channel` on `engine`, or on the current engine if `None`.
+    pub fn note_on(
+        &mut self,
+        engine: Option<SynthEngineType>,
+        channel: u8,
+        note: MidiNote,
+        vel: u8,
+    ) {
+        let engine = engine.unwrap_or(self.engine_type);
+        let i = engine as usize;
+        self.held[channel as usize & 0x0f][note as usize & 0x7f] = Some(engine);
+        self.pedals[i].note_on(note);
+        self.engines[i].play(note, vel);
+    }
+
+    /// stops `note` from `channel` on the engine it was played on, unless a pedal is holding it.
+    /// a note that wasn't played goes to `engine`, or to the current engine if `None`.
+    pub fn note_off(&mut self, engine: Option<SynthEngineType>, channel: u8, note: MidiNote) {
+        let held = self.held[channel as usize & 0x0f][note as usize & 0x7f].take();
+        let i = held.or(engine).unwrap_or(self.engine_type) as usize;
+
+        if self.pedals[i].note_off(note) {
+            self.engines[i].stop(note);
+        }
+    }
+
+    /// the engine `note` from `channel` was played on, while it's down.
+    fn played_on(&self, channel: u8, note: MidiNote) -> Option<usize> {
+        self.held[channel as usize & 0x0f][note as usize & 0x7f].map(|engine| engine as usize)
+    }
+
+    /// bends `engine`, or the current engine if `None`, to the 14 bit pitch bend `value`.
+    pub fn pitch_bend(&mut self, engine: Option<SynthEngineType>, value: u16) {
+        let i = engine.unwrap_or(self.engine_type) as usize;
+
+        if value == BEND_CENTER {
+            self.engines[i].unbend();
+        } else {
+            let semitones = self.bend_ranges[i].semitones(value);
+            self.engines[i].bend(semitones);
+        }
+    }
+
+    /// sends aftertouch to `engine`, or to the current engine if `None`. it presses on `note`
+    /// alone if it's given, or on every note if not.
+    pub fn aftertouch(
+        &mut self,
+        engine: Option<SynthEngineType>,
+        note: Option<MidiNote>,
+        value: u8,
+    ) {
+        let pressure = value.min(127) as f32 / 127.0;
+
+        match note {
+            Some(note) => self.engine_for(engine).poly_aftertouch(note, pressure),
+            None => self.engine_for(engine).aftertouch(pressure),
+        }
+    }
+
+    /// handles a CC sent to `engine`, or to the current engine if `None`.
+    pub fn control_change(&mut self, engine: Option<SynthEngineType>, control: u8, value: u8) {
+        let i = engine.unwrap_or(self.engine_type) as usize;
+
+        if control == SUSTAIN_CC || control == SOSTENUTO_CC {
+            let (pedals, engine) = (&mut self.pedals[i], &mut self.engines[i]);
+            let down = value >= PEDAL_DOWN;
+
+            if control == SUSTAIN_CC {
+                pedals.set_sustain(down, |note| engine.stop(note));
+            } else {
+                pedals.set_sostenuto(down, |note| engine.stop(note));
+            }
+
+            return;
+        }
+
+        if let SynthModule::WaveTable(wt) = self.engine_for(engine) {
+            wt.synth.midi_input(&MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlEvent { control, value },
+            ));
+            return;
+        }
+
+        let value = value as f32 / 127.0;
+        let knob = self
+            .knob_ccs
+            .iter()
+            .position(|cc| *cc == control)
+            .and_then(|i| Knob::iter().nth(i));
+
+        match knob {
+            // the effect only has four knobs.
+            Some(knob) if self.target_effects && (knob as usize) < 4 => {
+                self.get_effect().knob(knob, value)
+            }
+            Some(knob) => self.engine_for(engine).knob(knob, value),
+            None if control == 1 => self.engine_for(engine).volume_swell(value),
+            None => {
+                // info!("CC message => {control}-{value}");
+                false
+            }
+        };
+    }
+
+    pub fn all_notes_off(&mut self) {
+        self.held = [[None; 128]; MIDI_CHANNELS];
+
+        for (engine, pedals) in self.engines.iter_mut().zip(self.pedals.iter_mut()) {
+            pedals.reset();
+            (0..=127).for_each(|note| engine.stop(note));
+        }
+    }
+
+    /// what `scope_source` played in the last buffer, or `None` if the scope watches the output.
+    pub fn scope_samples(&self) -> Option<&[f32]> {
+        self.scope_source.map(|_| &self.scope_buf[..])
+    }
+
+    /// copies of the engines and effects, detached like `detached_clone`.
+    pub fn parts(&self) -> SynthParts {
+        let synth = self.detached_clone();
+
+        SynthParts {
+            engines: synth.engines,
+            effects: synth.effects,
+        }
+    }
+
+    /// trades the engines and effects for `parts`, without allocating so it's safe on the audio
+    /// thread. `parts` is left holding the old ones.
+    pub fn swap_parts(&mut self, parts: &mut SynthParts) {
+        std::mem::swap(&mut self.engines, &mut parts.engines);
+        std::mem::swap(&mut self.effects, &mut parts.effects);
+    }
+
     // pub fn route_lfo(&mut self, )
     // TODO: mod route
 }
 
 impl SampleGen for Synth {
     fn get_sample(&mut self) -> f32 {
+        self.get_frame().to_mono()
+    }
+
+    fn get_frame(&mut self) -> StereoFrame {
         // let engine = self.engines.index_mut(self.engine_type as usize);
 
         if let Some(target) = self.lfo_target
@@ -195,39 +553,137 @@ impl SampleGen for Synth {
             }
         }
 
-        // // let n_engines = self.engines.len();
-        // let mut n_samples = 1;
-        // // let mut sample = 0.0; // self.get_engine().get_sample() * 1.8;
-        //
-        // let mut samples: Vec<f32> = self
-        //     .engines
-        //     .iter_mut()
-        //     .map(|engine| {
-        //         let samp = engine.get_sample();
-        //
-        //         if samp != 0.0 {
-        //             // info!("{engine:?}");
-        //             n_samples += 1;
-        //         }
-        //
-        //         samp
-        //     })
-        //     .collect();
-        // samples[self.engine_type as usize] = samples[self.engine_type as usize] * 2.0;
-        //
-        // // let mut sample = samples.into_iter().sum();
-        //
-        // let bias = 1.0 / (n_samples as f32);
-        // let sample = samples.into_iter().sum::<f32>() * 0.8 * bias;
-        let sample = self.engines[self.engine_type as usize].get_sample();
-
-        if !self.effect_power || self.engine_type == SynthEngineType::WaveTable {
-            return sample;
+        let mut wet = StereoFrame::default();
+        let mut dry = StereoFrame::default();
+        let scope_source = self.scope_source.map(|engine| engine as usize);
+        self.scope_sample = 0.0;
+
+        for (i, ((engine, channel), meter)) in self
+            .engines
+            .iter_mut()
+            .zip(self.mixer.iter())
+            .zip(self.meters.engines.iter_mut())
+            .enumerate()
+        {
+            if !channel.audible() || !engine.is_active() {
+                continue;
+            }
+
+            let sample = engine.get_sample() * channel.level;
+            meter.add(sample);
+
+            if scope_source == Some(i) {
+                self.scope_sample = sample;
+            }
+
+            let frame = StereoFrame::panned(sample, channel.pan);
+
+            // the wave table synth has its own effects.
+            if let SynthModule::WaveTable(_) = engine {
+                dry += frame;
+            } else {
+                wet += frame;
+            }
+        }
+
+        if self.effect_power {
+            self.get_effect().take_input(wet);
+            wet = self.get_effect().get_frame();
+        }
+
+        self.meters.mix.add_frame(wet + dry);
+        let frame = self.master.process(wet + dry);
+        self.meters.master.add_frame(frame);
+
+        frame
+    }
+
+    /// sums every audible engine into `buffer`. engines with no voices sounding aren't rendered.
+    fn get_frames(&mut self, buffer: &mut [StereoFrame]) {
+        self.scope_buf.clear();
+
+        if self.lfo_target.is_some() && self.lfo_routed {
+            // the lfo nudges its target every sample so fall back to sample by sample rendering.
+            for frame in buffer.iter_mut() {
+                *frame = self.get_frame();
+
+                if self.scope_source.is_some() {
+                    self.scope_buf.push(self.scope_sample);
+                }
+            }
+
+            return;
+        }
+
+        let scope_source = self.scope_source.map(|engine| engine as usize);
+
+        if self.mono_buf.len() < buffer.len() {
+            self.mono_buf.resize(buffer.len(), 0.0);
         }
 
-        self.get_effect().take_input(sample);
+        let mono = &mut self.mono_buf[..buffer.len()];
+        let mut has_dry = false;
+        buffer.fill(StereoFrame::default());
+
+        for (i, ((engine, channel), meter)) in self
+            .engines
+            .iter_mut()
+            .zip(self.mixer.iter())
+            .zip(self.meters.engines.iter_mut())
+            .enumerate()
+        {
+            if !channel.audible() || !engine.is_active() {
+                continue;
+            }
+
+            engine.get_block(mono);
+            mono.iter()
+                .for_each(|sample| meter.add(sample * channel.level));
+
+            if scope_source == Some(i) {
+                self.scope_buf
+                    .extend(mono.iter().map(|sample| sample * channel.level));
+            }
+
+            let out = if let SynthModule::WaveTable(_) = engine {
+                if !has_dry {
+                    self.dry_buf.clear();
+                    self.dry_buf.resize(buffer.len(), StereoFrame::default());
+                    has_dry = true;
+                }
 
-        self.get_effect().get_sample()
+                &mut self.dry_buf[..]
+            } else {
+                &mut *buffer
+            };
+
+            for (frame, sample) in out.iter_mut().zip(mono.iter()) {
+                *frame += StereoFrame::panned(*sample * channel.level, channel.pan);
+            }
+        }
+
+        if scope_source.is_some() {
+            // a silent or muted engine still shows up as a flat line.
+            self.scope_buf.resize(buffer.len(), 0.0);
+        }
+
+        if self.effect_power {
+            self.effects[self.effect_type as usize].process_block(buffer);
+        }
+
+        if has_dry {
+            for (frame, dry) in buffer.iter_mut().zip(self.dry_buf.iter()) {
+                *frame += *dry;
+            }
+        }
+
+        buffer
+            .iter()
+            .for_each(|frame| self.meters.mix.add_frame(*frame));
+        self.master.process_block(buffer);
+        buffer
+            .iter()
+            .for_each(|frame| self.meters.master.add_frame(*frame));
     }
 }
 
@@ -238,61 +694,93 @@ impl MidiControlled for Synth {
         //     // return;
         // }
 
+        if self.mpe_input(message) {
+            return;
+        }
+
         match *message {
             MidiMessage::Invalid => {
                 error!("system received an invalid MIDI message.");
             }
-            MidiMessage::NoteOn(_, KeyEvent { key, value }) => {
+            MidiMessage::NoteOn(channel, KeyEvent { key, value }) => {
                 debug!("playing note: {key}");
-                self.get_engine().play(key, value)
+                self.note_on(
+                    self.midi_routes[channel as usize],
+                    channel as u8,
+                    key,
+                    value,
+                )
             }
-            MidiMessage::NoteOff(_, KeyEvent { key, value: _ }) => self.get_engine().stop(key),
-            MidiMessage::PitchBend(_, lsb, msb) => {
-                let bend = i16::from_le_bytes([lsb, msb]) as f32 / (32_000.0 * 0.5) - 1.0;
-
-                if bend > 0.02 || bend < -0.020 {
-                    self.get_engine().bend(bend);
-                    // send();
-                } else {
-                    self.get_engine().unbend();
-                    // send();
-                }
+            MidiMessage::NoteOff(channel, KeyEvent { key, value: _ }) => {
+                self.note_off(self.midi_routes[channel as usize], channel as u8, key)
             }
-            MidiMessage::ControlChange(_, ControlEvent { control, value }) => {
-                let value = value as f32 / 127.0;
-                let effects = self.target_effects;
-
-                match self.get_engine() {
-                    SynthModule::WaveTable(wt) => {
-                        wt.synth.midi_input(message);
-                    }
-                    engine => {
-                        match control {
-                            70 if effects => self.get_effect().knob_1(value),
-                            71 if effects => self.get_effect().knob_2(value),
-                            72 if effects => self.get_effect().knob_3(value),
-                            73 if effects => self.get_effect().knob_4(value),
-                            70 if !effects => self.get_engine().knob_1(value),
-                            71 if !effects => self.get_engine().knob_2(value),
-                            72 if !effects => self.get_engine().knob_3(value),
-                            73 if !effects => self.get_engine().knob_4(value),
-                            74 => engine.knob_5(value),
-                            75 => engine.knob_6(value),
-                            76 => engine.knob_7(value),
-                            77 => engine.knob_8(value),
-                            1 => engine.volume_swell(value),
-                            _ => {
-                                // info!("CC message => {control}-{value}");
-                                false
-                            }
-                        };
-                    }
-                }
-                // if self.engine_type == SynthEngineType::WaveTable {
-                // } else {
-                // }
+            MidiMessage::PitchBend(channel, lsb, msb) => self.pitch_bend(
+                self.midi_routes[channel as usize],
+                pitch_bend_value(lsb, msb),
+            ),
+            MidiMessage::ControlChange(channel, ControlEvent { control, value }) => {
+                self.control_change(self.midi_routes[channel as usize], control, value)
+            }
+            MidiMessage::ChannelPressure(channel, value) => {
+                self.aftertouch(self.midi_routes[channel as usize], None, value)
+            }
+            MidiMessage::PolyKeyPressure(channel, KeyEvent { key, value }) => {
+                self.aftertouch(self.midi_routes[channel as usize], Some(key), value)
             }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::set_sample_rate;
+    use synth_common::env::RELEASE;
+
+    /// true if the organ is sounding `note` and hasn't been told to let go of it.
+    fn organ_holding(synth: &Synth, note: MidiNote) -> bool {
+        let SynthModule::B3Organ(organ) = &synth.engines[SynthEngineType::B3Organ as usize] else {
+            unreachable!("the organ isn't where the organ goes");
+        };
+
+        // the organ plays an octave down.
+        organ
+            .osc_s
+            .iter()
+            .any(|osc| osc.playing == Some(note - 12) && osc.env_filter.phase != RELEASE)
+    }
+
+    #[test]
+    fn note_off_follows_the_note_on_after_an_engine_switch() {
+        set_sample_rate(48_000);
+        let mut synth = Synth::new();
+        synth.set_engine(SynthEngineType::B3Organ);
+
+        synth.note_on(None, 0, 60, 100);
+        assert!(organ_holding(&synth, 60));
+
+        synth.set_engine(SynthEngineType::SubSynth);
+        synth.note_off(None, 0, 60);
+        assert!(!organ_holding(&synth, 60));
+    }
+
+    #[test]
+    fn note_off_follows_the_note_on_after_a_route_change() {
+        set_sample_rate(48_000);
+        let mut synth = Synth::new();
+        synth.set_engine(SynthEngineType::SubSynth);
+        synth.set_midi_route(1, Some(SynthEngineType::B3Organ));
+
+        let key = KeyEvent {
+            key: 60,
+            value: 100,
+        };
+        synth.midi_input(&MidiMessage::NoteOn(Channel::Ch2, key));
+        assert!(organ_holding(&synth, 60));
+
+        synth.set_midi_route(1, None);
+        synth.midi_input(&MidiMessage::NoteOff(Channel::Ch2, key));
+        assert!(!organ_holding(&synth, 60));
+    }
+}
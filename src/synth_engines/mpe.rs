@@ -0,0 +1,328 @@
+use super::{pitch_bend_value, BendRange, Synth, SynthEngine};
+use log::*;
+use midi_control::{ControlEvent, KeyEvent, MidiMessage, MidiNote};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// the CC member channels send timbre on.
+pub const TIMBRE_CC: u8 = 74;
+/// the master channel of the lower zone, its members count up from the next channel.
+pub const LOWER_MASTER: u8 = 0;
+/// the master channel of the upper zone, its members count down from the channel before.
+pub const UPPER_MASTER: u8 = 15;
+/// how far member channels bend until an RPN says otherwise, as the MPE spec asks.
+pub const MEMBER_BEND_RANGE: f32 = 48.0;
+
+const RPN_MSB_CC: u8 = 101;
+const RPN_LSB_CC: u8 = 100;
+const NRPN_MSB_CC: u8 = 99;
+const NRPN_LSB_CC: u8 = 98;
+const DATA_ENTRY_CC: u8 = 6;
+/// what a channel's RPN is while none is selected.
+const RPN_NULL: [u8; 2] = [0x7f, 0x7f];
+const RPN_BEND_RANGE: [u8; 2] = [0, 0];
+const RPN_MPE_CONFIG: [u8; 2] = [0, 6];
+
+/// how many member channels each MPE zone has. a zone with none is off, and with both off MIDI
+/// is played as usual.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MpeZones {
+    pub lower: u8,
+    pub upper: u8,
+}
+
+impl MpeZones {
+    /// these zones with `lower` members in the lower zone. the upper zone shrinks to make room.
+    pub fn with_lower(self, lower: u8) -> Self {
+        let lower = lower.min(15);
+
+        Self {
+            lower,
+            upper: self.upper.min(14_u8.saturating_sub(lower)),
+        }
+    }
+
+    /// these zones with `upper` members in the upper zone. the lower zone shrinks to make room.
+    pub fn with_upper(self, upper: u8) -> Self {
+        let upper = upper.min(15);
+
+        Self {
+            lower: self.lower.min(14_u8.saturating_sub(upper)),
+            upper,
+        }
+    }
+
+    /// the zone `channel`, from 0, is in.
+    pub fn zone(&self, channel: u8) -> Option<Zone> {
+        if self.lower > 0 && channel <= LOWER_MASTER + self.lower {
+            Some(Zone::Lower)
+        } else if self.upper > 0 && channel >= UPPER_MASTER - self.upper {
+            Some(Zone::Upper)
+        } else {
+            None
+        }
+    }
+
+    /// the member channels of `zone`.
+    pub fn members(&self, zone: Zone) -> impl Iterator<Item = u8> {
+        match zone {
+            Zone::Lower => (LOWER_MASTER + 1)..=(LOWER_MASTER + self.lower),
+            Zone::Upper => (UPPER_MASTER - self.upper)..=(UPPER_MASTER - 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Lower,
+    Upper,
+}
+
+impl Zone {
+    pub fn master(&self) -> u8 {
+        match self {
+            Self::Lower => LOWER_MASTER,
+            Self::Upper => UPPER_MASTER,
+        }
+    }
+}
+
+/// an RPN set with data entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rpn {
+    /// pitch bend sensitivity, in semitones.
+    BendRange(u8),
+    /// the MPE configuration message, how many member channels the zone gets.
+    MpeConfig(u8),
+}
+
+/// the MPE receiver. each member channel plays one note at a time, its bend, pressure and timbre
+/// go to the voice playing that note.
+#[derive(Debug, Clone)]
+pub struct Mpe {
+    pub zones: MpeZones,
+    /// how far each zone's member channels bend, lower zone first.
+    pub member_bend: [f32; 2],
+    /// the bend of each zone's master channel in semitones, it adds to every member's bend.
+    master_bend: [f32; 2],
+    /// the note each channel is playing.
+    notes: [Option<MidiNote>; 16],
+    /// each channel's bend in semitones.
+    bends: [f32; 16],
+    pressures: [f32; 16],
+    timbres: [f32; 16],
+    /// the RPN each channel has selected, data entry sets it.
+    rpns: [[u8; 2]; 16],
+}
+
+impl Default for Mpe {
+    fn default() -> Self {
+        Self {
+            zones: MpeZones::default(),
+            member_bend: [MEMBER_BEND_RANGE; 2],
+            master_bend: [0.0; 2],
+            notes: [None; 16],
+            bends: [0.0; 16],
+            pressures: [0.0; 16],
+            timbres: [0.0; 16],
+            rpns: [RPN_NULL; 16],
+        }
+    }
+}
+
+impl Mpe {
+    /// changes the zones, every member channel starts over.
+    pub fn set_zones(&mut self, zones: MpeZones) {
+        info!("MPE zones: {} lower, {} upper", zones.lower, zones.upper);
+
+        *self = Self {
+            zones,
+            rpns: self.rpns,
+            ..Self::default()
+        };
+    }
+
+    /// keeps track of the RPN selected on `channel`. returns the RPN if this CC set one.
+    pub fn rpn(&mut self, channel: u8, control: u8, value: u8) -> Option<Rpn> {
+        let rpn = &mut self.rpns[channel as usize & 0xf];
+
+        match control {
+            RPN_MSB_CC => rpn[0] = value,
+            RPN_LSB_CC => rpn[1] = value,
+            // data entry after these is for an NRPN.
+            NRPN_MSB_CC | NRPN_LSB_CC => *rpn = RPN_NULL,
+            DATA_ENTRY_CC if *rpn == RPN_BEND_RANGE => return Some(Rpn::BendRange(value)),
+            DATA_ENTRY_CC if *rpn == RPN_MPE_CONFIG => return Some(Rpn::MpeConfig(value)),
+            _ => {}
+        }
+
+        None
+    }
+}
+
+impl Synth {
+    /// sets the MPE zones, the lower zone wins if they overlap.
+    pub fn set_mpe_zones(&mut self, zones: MpeZones) -> bool {
+        self.mpe.set_zones(zones.with_lower(zones.lower));
+
+        true
+    }
+
+    /// handles `message` if it's for an MPE zone or sets an RPN. returns false if it should be
+    /// played as ordinary MIDI.
+    pub fn mpe_input(&mut self, message: &MidiMessage) -> bool {
+        let channel = match *message {
+            MidiMessage::NoteOn(channel, _)
+            | MidiMessage::NoteOff(channel, _)
+            | MidiMessage::PitchBend(channel, _, _)
+            | MidiMessage::ChannelPressure(channel, _)
+            | MidiMessage::PolyKeyPressure(channel, _)
+            | MidiMessage::ControlChange(channel, _) => channel as u8,
+            _ => return false,
+        };
+
+        if let MidiMessage::ControlChange(_, ControlEvent { control, value }) = *message
+            && let Some(rpn) = self.mpe.rpn(channel, control, value)
+        {
+            self.rpn_input(channel, rpn);
+            return true;
+        }
+
+        let Some(zone) = self.mpe.zones.zone(channel) else {
+            return false;
+        };
+        let engine = self.midi_routes[zone.master() as usize];
+        let i = engine.unwrap_or(self.engine_type) as usize;
+        let ch = channel as usize;
+
+        if channel == zone.master() {
+            // notes, pressure and CCs on the master channel are for the whole zone, only its bend
+            // has to be added to the members'.
+            let MidiMessage::PitchBend(_, lsb, msb) = *message else {
+                return false;
+            };
+
+            self.mpe.master_bend[zone as usize] =
+                self.bend_ranges[i].semitones(pitch_bend_value(lsb, msb));
+
+            for member in self.mpe.zones.members(zone) {
+                if let Some(note) = self.mpe.notes[member as usize] {
+                    let bend =
+                        self.mpe.master_bend[zone as usize] + self.mpe.bends[member as usize];
+                    let i = self.played_on(member, note).unwrap_or(i);
+                    self.engines[i].note_bend(note, bend);
+                }
+            }
+
+            return true;
+        }
+
+        match *message {
+            MidiMessage::NoteOn(_, KeyEvent { key, value }) => {
+                self.mpe.notes[ch] = Some(key);
+                self.note_on(engine, channel, key, value);
+
+                // the channel's expression was sent before the note so it starts with it.
+                let bend = self.mpe.master_bend[zone as usize] + self.mpe.bends[ch];
+
+                if bend != 0.0 {
+                    self.engines[i].note_bend(key, bend);
+                }
+
+                self.engines[i].poly_aftertouch(key, self.mpe.pressures[ch]);
+                self.engines[i].note_timbre(key, self.mpe.timbres[ch]);
+            }
+            MidiMessage::NoteOff(_, KeyEvent { key, value: _ }) => {
+                if self.mpe.notes[ch] == Some(key) {
+                    self.mpe.notes[ch] = None;
+                }
+
+                self.note_off(engine, channel, key);
+            }
+            MidiMessage::PitchBend(_, lsb, msb) => {
+                let range = self.mpe.member_bend[zone as usize];
+                let range = BendRange {
+                    up: range,
+                    down: range,
+                };
+                self.mpe.bends[ch] = range.semitones(pitch_bend_value(lsb, msb));
+
+                if let Some(note) = self.mpe.notes[ch] {
+                    let bend = self.mpe.master_bend[zone as usize] + self.mpe.bends[ch];
+                    let i = self.played_on(channel, note).unwrap_or(i);
+                    self.engines[i].note_bend(note, bend);
+                }
+            }
+            MidiMessage::ChannelPressure(_, value) => {
+                self.mpe.pressures[ch] = value.min(127) as f32 / 127.0;
+
+                if let Some(note) = self.mpe.notes[ch] {
+                    let i = self.played_on(channel, note).unwrap_or(i);
+                    self.engines[i].poly_aftertouch(note, self.mpe.pressures[ch]);
+                }
+            }
+            MidiMessage::PolyKeyPressure(_, KeyEvent { key, value }) => {
+                self.aftertouch(engine, Some(key), value)
+            }
+            MidiMessage::ControlChange(_, ControlEvent { control, value }) => {
+                if control == TIMBRE_CC {
+                    self.mpe.timbres[ch] = value.min(127) as f32 / 127.0;
+
+                    if let Some(note) = self.mpe.notes[ch] {
+                        let i = self.played_on(channel, note).unwrap_or(i);
+                        self.engines[i].note_timbre(note, self.mpe.timbres[ch]);
+                    }
+                } else {
+                    self.control_change(engine, control, value);
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn rpn_input(&mut self, channel: u8, rpn: Rpn) {
+        match rpn {
+            Rpn::BendRange(semitones) => {
+                let semitones = semitones as f32;
+
+                match self.mpe.zones.zone(channel) {
+                    Some(zone) if channel != zone.master() => {
+                        self.mpe.member_bend[zone as usize] = semitones
+                    }
+                    zone => {
+                        let channel = zone.map_or(channel, |zone| zone.master());
+                        let engine = self.midi_routes[channel as usize].unwrap_or(self.engine_type);
+
+                        self.set_bend_range(
+                            engine,
+                            BendRange {
+                                up: semitones,
+                                down: semitones,
+                            },
+                        );
+                    }
+                }
+            }
+            Rpn::MpeConfig(members) => {
+                let zones = match channel {
+                    LOWER_MASTER => self.mpe.zones.with_lower(members),
+                    UPPER_MASTER => self.mpe.zones.with_upper(members),
+                    _ => {
+                        warn!("ignoring an MPE configuration message on channel {channel}");
+                        return;
+                    }
+                };
+
+                self.mpe.set_zones(zones);
+            }
+        }
+    }
+}
@@ -5,6 +5,7 @@ use crate::{
         synth_common::{
             env::{ATTACK, DECAY, RELEASE, SUSTAIN},
             lfo::LFO,
+            velocity::Velocity,
             WaveTable, WAVE_TABLE_SIZE,
         },
         LfoInput, SynthEngine,
@@ -14,6 +15,8 @@ use crate::{
 use midi_control::MidiNote;
 
 pub const VOICES: usize = 10;
+/// how much deeper full aftertouch makes the vibrato.
+const PRESSURE_VIBRATO: f32 = 3.0;
 
 fn build_sine_table(overtones: &[Overtone]) -> WaveTable {
     let mut wave_table = [0.0; WAVE_TABLE_SIZE];
@@ -50,6 +53,10 @@ pub struct Organ {
     pub lfo: LFO,
     pub volume: f32,
     pub speaker_speed: f32,
+    /// the leslie setting, from 0.0 to 1.0, that `speaker_speed` comes from.
+    pub leslie: f32,
+    /// the organ has no filter so only `velocity.amp` does anything.
+    pub velocity: Velocity,
     // pub chorus: Chorus,
     // pub reverb: Reverb,
     // lfo_target: Option<Param>,
@@ -103,7 +110,8 @@ impl Organ {
             },
         ];
         let wave_table = build_sine_table(&overtones);
-        let speaker_speed = (440.0 * 0.4) / 60.0;
+        let leslie = 0.4;
+        let speaker_speed = (440.0 * leslie) / 60.0;
         let mut lfo = LFO::new();
         lfo.set_frequency(speaker_speed);
 
@@ -115,6 +123,8 @@ impl Organ {
             lfo,
             volume: 1.0,
             speaker_speed,
+            leslie,
+            velocity: Velocity::default(),
             // lfo_input: 0.0,
             // lfo_target: None,
             lfo_target: LfoInput::default(),
@@ -135,7 +145,7 @@ impl Organ {
             // for osc in osc_s {
             if osc.playing.is_some() {
                 // osc.for_each(|(osc, _offset)| {
-                osc.vibrato(lfo_sample);
+                osc.vibrato(lfo_sample * (1.0 + osc.pressure * PRESSURE_VIBRATO));
                 // println!("playing");
                 sample += osc.get_sample(&self.wave_table);
                 // println!(
@@ -153,7 +163,7 @@ impl Organ {
         sample.tanh()
     }
 
-    pub fn play(&mut self, midi_note: MidiNote, _velocity: u8) {
+    pub fn play(&mut self, midi_note: MidiNote, velocity: u8) {
         let midi_note = if midi_note >= 12 {
             midi_note - 12
         } else {
@@ -180,7 +190,7 @@ impl Organ {
                 //     // println!("offset {} -> {}", offset, (offset.abs() as u8));
                 //     midi_note - (offset.abs() as u8)
                 // };
-                osc.press(midi_note);
+                osc.press(midi_note, self.velocity.gain(velocity));
                 osc.playing = Some(midi_note);
                 // println!("playing note on osc {i}");
 
@@ -216,12 +226,31 @@ impl Organ {
         // }
     }
 
-    pub fn bend_all(&mut self, bend: f32) {
+    /// the voices playing `midi_note`, or every playing voice if `None`.
+    fn voices(
+        &mut self,
+        midi_note: Option<MidiNote>,
+    ) -> impl Iterator<Item = &mut Oscillator> + '_ {
+        // notes play an octave down, see `play`.
+        let midi_note = midi_note.map(|note| note.wrapping_sub(12));
+
+        self.osc_s.iter_mut().filter(move |osc| {
+            osc.playing.is_some() && midi_note.is_none_or(|note| osc.playing == Some(note))
+        })
+    }
+
+    /// sets the aftertouch of `midi_note`, or of every note if `None`.
+    pub fn set_pressure(&mut self, midi_note: Option<MidiNote>, pressure: f32) {
+        self.voices(midi_note)
+            .for_each(|osc| osc.pressure = pressure)
+    }
+
+    pub fn bend_all(&mut self, semitones: f32) {
         // for (osc_s, _offset) in self.osc_s.iter_mut() {
         // for osc in osc_s {
         for osc in self.osc_s.iter_mut() {
             if osc.playing.is_some() {
-                osc.bend(bend);
+                osc.bend(semitones);
             }
         }
         // }
@@ -279,6 +308,7 @@ impl Organ {
     }
 
     pub fn set_leslie_speed(&mut self, speed: f32) {
+        self.leslie = speed;
         self.speaker_speed = (440.0 * speed) / 60.0;
         self.lfo.set_frequency(self.speaker_speed);
         self.lfo.set_volume(speed);
@@ -298,6 +328,10 @@ impl SynthEngine for Organ {
         "Organ".into()
     }
 
+    fn is_active(&self) -> bool {
+        self.osc_s.iter().any(|osc| osc.playing.is_some())
+    }
+
     fn play(&mut self, note: MidiNote, velocity: u8) {
         self.play(note, velocity)
     }
@@ -306,8 +340,24 @@ impl SynthEngine for Organ {
         self.stop(note)
     }
 
-    fn bend(&mut self, amount: f32) {
-        self.bend_all(amount)
+    fn bend(&mut self, semitones: f32) {
+        self.bend_all(semitones)
+    }
+
+    fn aftertouch(&mut self, pressure: f32) {
+        self.set_pressure(None, pressure);
+    }
+
+    fn poly_aftertouch(&mut self, note: MidiNote, pressure: f32) {
+        self.set_pressure(Some(note), pressure);
+    }
+
+    fn note_bend(&mut self, note: MidiNote, semitones: f32) {
+        self.voices(Some(note)).for_each(|osc| osc.bend(semitones))
+    }
+
+    fn note_timbre(&mut self, note: MidiNote, timbre: f32) {
+        self.voices(Some(note)).for_each(|osc| osc.timbre = timbre)
     }
 
     fn volume_swell(&mut self, amount: f32) -> bool {
@@ -316,6 +366,16 @@ impl SynthEngine for Organ {
         true
     }
 
+    fn velocity(&self) -> Option<Velocity> {
+        Some(self.velocity)
+    }
+
+    fn set_velocity(&mut self, velocity: Velocity) -> bool {
+        self.velocity = velocity;
+
+        true
+    }
+
     fn get_params(&self) -> HashMap<Knob, f32> {
         let mut map = HashMap::default();
 
@@ -338,7 +398,7 @@ impl SynthEngine for Organ {
         map.insert(GuiParam::B, self.osc_s[0].env_filter.base_params[DECAY]);
         map.insert(GuiParam::C, self.osc_s[0].env_filter.base_params[SUSTAIN]);
         map.insert(GuiParam::D, self.osc_s[0].env_filter.base_params[RELEASE]);
-        map.insert(GuiParam::E, self.speaker_speed);
+        map.insert(GuiParam::E, self.leslie);
 
         map
     }
@@ -348,6 +408,18 @@ impl SampleGen for Organ {
     fn get_sample(&mut self) -> f32 {
         self.get_sample()
     }
+
+    fn get_block(&mut self, buffer: &mut [f32]) {
+        if !self.is_active() {
+            buffer.fill(0.0);
+
+            return;
+        }
+
+        for sample in buffer.iter_mut() {
+            *sample = self.get_sample();
+        }
+    }
 }
 
 impl KnobCtrl for Organ {
@@ -2,6 +2,9 @@
 use crate::synth_engines::synth_common::{env::ADSR, osc::WavetableOscillator, WaveTable};
 use std::sync::Arc;
 
+/// how hard full MPE timbre drives a voice.
+const TIMBRE_DRIVE: f32 = 4.0;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Overtone {
     /// the frequency of the overtone relative to the fundimental
@@ -19,6 +22,12 @@ pub struct Oscillator {
     frequency: f32,
     base_frequency: f32,
     note_space: f32,
+    /// the volume of the note, from its velocity.
+    gain: f32,
+    /// aftertouch, from 0.0 to 1.0, deepens the vibrato.
+    pub pressure: f32,
+    /// MPE timbre, from 0.0 to 1.0, overdrives the voice.
+    pub timbre: f32,
     // pub low_pass: LowPass,
 }
 
@@ -31,6 +40,9 @@ impl Oscillator {
             frequency: 0.0,
             base_frequency: 0.0,
             note_space: 2.0_f32.powf(1.0 / 12.0),
+            gain: 1.0,
+            pressure: 0.0,
+            timbre: 0.0,
             // low_pass: LowPass::new(),
         }
     }
@@ -39,8 +51,11 @@ impl Oscillator {
         self.env_filter.pressed()
     }
 
-    pub fn press(&mut self, midi_note: u8) {
+    pub fn press(&mut self, midi_note: u8, gain: f32) {
         self.env_filter.press();
+        self.gain = gain;
+        self.pressure = 0.0;
+        self.timbre = 0.0;
         self.frequency = Self::get_freq(midi_note);
         self.base_frequency = self.frequency;
 
@@ -80,8 +95,16 @@ impl Oscillator {
             self.playing = None;
         }
 
+        let sample = if self.timbre > 0.0 {
+            let drive = 1.0 + self.timbre * TIMBRE_DRIVE;
+
+            (sample * drive).tanh() / drive.tanh()
+        } else {
+            sample
+        };
+
         // self.low_pass.get_sample(sample, env)
-        sample * env
+        sample * env * self.gain
     }
 
     pub fn vibrato(&mut self, amt: f32) {
@@ -101,9 +124,10 @@ impl Oscillator {
         self.wt_osc.set_frequency(self.frequency + adjust_amt)
     }
 
-    pub fn bend(&mut self, bend: f32) {
+    /// bends the note `semitones` up, or down if negative.
+    pub fn bend(&mut self, semitones: f32) {
         // println!("bending");
-        let new_freq = self.base_frequency * 2.0_f32.powf((bend * 3.0) / 12.0);
+        let new_freq = self.base_frequency * 2.0_f32.powf(semitones / 12.0);
         // + self.frequency;
         self.wt_osc.set_frequency(new_freq);
         // println!("frequency => {}", self.frequency);
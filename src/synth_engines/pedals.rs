@@ -0,0 +1,93 @@
+use midi_control::MidiNote;
+
+pub const SUSTAIN_CC: u8 = 64;
+pub const SOSTENUTO_CC: u8 = 66;
+/// pedal CCs at or above this are down.
+pub const PEDAL_DOWN: u8 = 64;
+
+/// the sustain and sostenuto pedals of one engine. note offs go through here so the ones a pedal
+/// is holding can be sent when it comes up.
+#[derive(Debug, Clone)]
+pub struct Pedals {
+    sustain: bool,
+    sostenuto: bool,
+    /// keys that are down.
+    held: [bool; 128],
+    /// keys that were down when the sostenuto pedal went down, it only holds these.
+    sostenuto_notes: [bool; 128],
+    /// keys that are up but still sounding because of a pedal.
+    deferred: [bool; 128],
+}
+
+impl Default for Pedals {
+    fn default() -> Self {
+        Self {
+            sustain: false,
+            sostenuto: false,
+            held: [false; 128],
+            sostenuto_notes: [false; 128],
+            deferred: [false; 128],
+        }
+    }
+}
+
+impl Pedals {
+    pub fn note_on(&mut self, note: MidiNote) {
+        let note = note as usize & 0x7f;
+        self.held[note] = true;
+        self.deferred[note] = false;
+    }
+
+    /// returns true if the note should stop now, false if a pedal is holding it.
+    pub fn note_off(&mut self, note: MidiNote) -> bool {
+        let note = note as usize & 0x7f;
+        self.held[note] = false;
+
+        if self.sustain || (self.sostenuto && self.sostenuto_notes[note]) {
+            self.deferred[note] = true;
+            false
+        } else {
+            true
+        }
+    }
+
+    /// puts the sustain pedal down or up. `stop` is called with every note it stops holding.
+    pub fn set_sustain(&mut self, down: bool, stop: impl FnMut(MidiNote)) {
+        self.sustain = down;
+
+        if !down {
+            self.release(stop);
+        }
+    }
+
+    /// puts the sostenuto pedal down or up. `stop` is called with every note it stops holding.
+    pub fn set_sostenuto(&mut self, down: bool, stop: impl FnMut(MidiNote)) {
+        if down && !self.sostenuto {
+            self.sostenuto_notes = self.held;
+        }
+
+        self.sostenuto = down;
+
+        if !down {
+            self.sostenuto_notes = [false; 128];
+            self.release(stop);
+        }
+    }
+
+    /// stops the deferred notes no pedal is holding any more.
+    fn release(&mut self, mut stop: impl FnMut(MidiNote)) {
+        for note in 0..128 {
+            let still_held = self.sustain || (self.sostenuto && self.sostenuto_notes[note]);
+
+            if self.deferred[note] && !still_held {
+                self.deferred[note] = false;
+                stop(note as MidiNote);
+            }
+        }
+    }
+
+    /// lifts both pedals without stopping anything, for when every note is being stopped anyway.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -2,8 +2,9 @@ use super::{
     build_sine_table, saw_tooth::SawToothOsc, OscType, SynthBackend, SynthOscilatorBackend,
 };
 use crate::{
+    sample_rate,
     synth_engines::synth_common::{env::ADSR, moog_filter::LowPass, WaveTable, WAVE_TABLE_SIZE},
-    SampleGen, SAMPLE_RATE,
+    SampleGen,
 };
 
 pub const N_OVERTONES: usize = 16;
@@ -23,7 +24,7 @@ impl WavetableOscillator {
         let wave_table = build_sine_table(&overtones);
 
         Self {
-            sample_rate: SAMPLE_RATE as f32,
+            sample_rate: sample_rate() as f32,
             index: 0.0,
             index_increment: 0.0,
             wave_table,
@@ -106,6 +107,8 @@ pub struct SynthOscillator {
     frequency: f32,
     base_frequency: f32,
     // note_space: f32,
+    /// the volume of the note, from its velocity.
+    gain: f32,
     pub low_pass: LowPass,
     // pub wave_table: WaveTable,
 }
@@ -120,6 +123,7 @@ impl SynthOscillator {
             frequency: 0.0,
             base_frequency: 0.0,
             // note_space: 2.0_f32.powf(1.0 / 12.0),
+            gain: 1.0,
             low_pass: LowPass::new(),
         }
     }
@@ -136,13 +140,18 @@ impl SynthOscillator {
         self.env_filter.pressed()
     }
 
-    pub fn press(&mut self, midi_note: u8) {
+    /// starts `midi_note` at `gain`, with the filter opened `brightness` further than its cutoff.
+    pub fn press(&mut self, midi_note: u8, gain: f32, brightness: f32) {
         self.env_filter.press();
         self.frequency = Self::get_freq(midi_note);
         self.base_frequency = self.frequency;
+        self.gain = gain;
 
         self.osc.set_frequency(self.frequency);
         self.low_pass.set_note(self.frequency);
+        self.low_pass.brightness = brightness;
+        self.low_pass.pressure = 0.0;
+        self.low_pass.timbre = 0.0;
         self.playing = Some(midi_note);
     }
 
@@ -160,7 +169,7 @@ impl SynthOscillator {
 
     pub fn get_sample(&mut self) -> f32 {
         let env = self.env_filter.get_samnple();
-        let sample = self.osc.get_sample() * env;
+        let sample = self.osc.get_sample() * env * self.gain;
 
         if env <= 0.0 {
             self.playing = None;
@@ -170,16 +179,10 @@ impl SynthOscillator {
         self.low_pass.get_sample(sample, env)
     }
 
-    pub fn bend(&mut self, bend: f32) {
+    /// bends the note `semitones` up, or down if negative.
+    pub fn bend(&mut self, semitones: f32) {
         // println!("bending");
-        let nudge = 2.0_f32.powf((bend * 3.0).abs() / 12.0);
-        let new_freq = if bend < 0.0 {
-            self.base_frequency / nudge
-        } else if bend > 0.0 {
-            self.base_frequency * nudge
-        } else {
-            self.base_frequency
-        };
+        let new_freq = self.base_frequency * 2.0_f32.powf(semitones / 12.0);
         // + self.frequency;
         self.osc.set_frequency(new_freq);
         // println!("frequency => {}", self.frequency);
@@ -1,13 +1,25 @@
 use super::{SynthOscilatorBackend, N_OVERTONES_SAW};
-use crate::{SampleGen, SAMPLE_RATE};
+use crate::{sample_rate, SampleGen};
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy)]
 struct STOsc {
+    sample_rate: f64,
     value: f64,
     inc: f64,
     dir: bool,
 }
 
+impl STOsc {
+    fn new() -> Self {
+        Self {
+            sample_rate: sample_rate() as f64,
+            value: 0.0,
+            inc: 0.0,
+            dir: false,
+        }
+    }
+}
+
 impl SampleGen for STOsc {
     fn get_sample(&mut self) -> f32 {
         if !self.dir {
@@ -24,7 +36,7 @@ impl SampleGen for STOsc {
 impl SynthOscilatorBackend for STOsc {
     fn set_frequency(&mut self, frequency: f32) {
         let n_peeks = frequency as f64 * 2.0;
-        self.inc = 2.0 / (SAMPLE_RATE as f64 / n_peeks);
+        self.inc = 2.0 / (self.sample_rate / n_peeks);
         self.value = -1.0;
     }
 
@@ -42,7 +54,7 @@ pub struct SawToothOsc {
 
 impl SawToothOsc {
     pub fn new() -> Self {
-        let mut osc = STOsc::default();
+        let mut osc = STOsc::new();
         osc.value = 1.0;
 
         Self {
@@ -2,7 +2,10 @@ use super::{osc::SynthOscillator, OscType};
 use crate::{
     pygame_coms::{GuiParam, Knob},
     synth_engines::{
-        synth_common::env::{ATTACK, DECAY, RELEASE, SUSTAIN},
+        synth_common::{
+            env::{ATTACK, DECAY, RELEASE, SUSTAIN},
+            velocity::Velocity,
+        },
         LfoInput, SynthEngine,
     },
     HashMap, KnobCtrl, SampleGen,
@@ -10,6 +13,10 @@ use crate::{
 use midi_control::MidiNote;
 
 pub const VOICES: usize = 10;
+/// how far full aftertouch opens the filter past its cutoff.
+const PRESSURE_CUTOFF: f32 = 0.5;
+/// how far full MPE timbre opens the filter past its cutoff.
+const TIMBRE_CUTOFF: f32 = 0.5;
 
 #[derive(Debug, Clone)]
 pub struct Synth {
@@ -19,6 +26,7 @@ pub struct Synth {
     pub volume: f32,
     pub mix: f32,
     pub osc_sync: bool,
+    pub velocity: Velocity,
     lfo_target: LfoInput,
 }
 
@@ -49,6 +57,7 @@ impl Synth {
             volume: 0.75,
             mix: 0.5,
             osc_sync: false,
+            velocity: Velocity::default(),
             lfo_target: LfoInput::default(),
         }
     }
@@ -109,7 +118,10 @@ impl Synth {
         // sample * self.volume
     }
 
-    pub fn play(&mut self, midi_note: MidiNote, _velocity: u8) {
+    pub fn play(&mut self, midi_note: MidiNote, velocity: u8) {
+        let gain = self.velocity.gain(velocity);
+        let brightness = self.velocity.brightness(velocity);
+
         // let midi_note = if midi_note >= 12 {
         //     midi_note - 12
         // } else {
@@ -140,7 +152,7 @@ impl Synth {
                         midi_note // - (offset.abs() as u8)
                     };
 
-                    osc.press(note);
+                    osc.press(note, gain, brightness);
                     osc.playing = Some(midi_note);
                     // println!("playing note on osc {i}");
 
@@ -175,11 +187,30 @@ impl Synth {
         }
     }
 
-    pub fn bend_all(&mut self, bend: f32) {
+    /// the voices playing `note`, or every playing voice if `None`.
+    fn voices(
+        &mut self,
+        note: Option<MidiNote>,
+    ) -> impl Iterator<Item = &mut SynthOscillator> + '_ {
+        self.osc_s
+            .iter_mut()
+            .flat_map(|(osc_s, _offset)| osc_s.iter_mut())
+            .filter(move |osc| {
+                osc.playing.is_some() && note.is_none_or(|note| osc.playing == Some(note))
+            })
+    }
+
+    /// sets the aftertouch of `note`, or of every note if `None`.
+    pub fn set_pressure(&mut self, note: Option<MidiNote>, pressure: f32) {
+        self.voices(note)
+            .for_each(|osc| osc.low_pass.pressure = pressure * PRESSURE_CUTOFF);
+    }
+
+    pub fn bend_all(&mut self, semitones: f32) {
         for (osc_s, _offset) in self.osc_s.iter_mut() {
             for osc in osc_s {
                 if osc.playing.is_some() {
-                    osc.bend(bend);
+                    osc.bend(semitones);
                 }
             }
         }
@@ -254,6 +285,18 @@ impl SampleGen for Synth {
     fn get_sample(&mut self) -> f32 {
         self.get_sample()
     }
+
+    fn get_block(&mut self, buffer: &mut [f32]) {
+        if !self.is_active() {
+            buffer.fill(0.0);
+
+            return;
+        }
+
+        for sample in buffer.iter_mut() {
+            *sample = self.get_sample();
+        }
+    }
 }
 
 impl SynthEngine for Synth {
@@ -261,6 +304,12 @@ impl SynthEngine for Synth {
         "Synth".into()
     }
 
+    fn is_active(&self) -> bool {
+        self.osc_s
+            .iter()
+            .any(|(osc_s, _offset)| osc_s.iter().any(|osc| osc.playing.is_some()))
+    }
+
     fn play(&mut self, note: MidiNote, velocity: u8) {
         self.play(note, velocity)
     }
@@ -269,8 +318,8 @@ impl SynthEngine for Synth {
         self.stop(note)
     }
 
-    fn bend(&mut self, amount: f32) {
-        self.bend_all(amount)
+    fn bend(&mut self, semitones: f32) {
+        self.bend_all(semitones)
     }
 
     fn get_params(&self) -> HashMap<Knob, f32> {
@@ -309,10 +358,37 @@ impl SynthEngine for Synth {
         map
     }
 
+    fn aftertouch(&mut self, pressure: f32) {
+        self.set_pressure(None, pressure);
+    }
+
+    fn poly_aftertouch(&mut self, note: MidiNote, pressure: f32) {
+        self.set_pressure(Some(note), pressure);
+    }
+
+    fn note_bend(&mut self, note: MidiNote, semitones: f32) {
+        self.voices(Some(note)).for_each(|osc| osc.bend(semitones))
+    }
+
+    fn note_timbre(&mut self, note: MidiNote, timbre: f32) {
+        self.voices(Some(note))
+            .for_each(|osc| osc.low_pass.timbre = timbre * TIMBRE_CUTOFF)
+    }
+
     fn volume_swell(&mut self, amount: f32) -> bool {
         self.volume = amount;
         false
     }
+
+    fn velocity(&self) -> Option<Velocity> {
+        Some(self.velocity)
+    }
+
+    fn set_velocity(&mut self, velocity: Velocity) -> bool {
+        self.velocity = velocity;
+
+        true
+    }
 }
 
 impl KnobCtrl for Synth {
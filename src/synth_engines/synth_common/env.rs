@@ -1,4 +1,4 @@
-use crate::SAMPLE_RATE;
+use crate::sample_rate;
 // use log::*;
 
 pub static UNPRESSED: usize = 0;
@@ -19,45 +19,47 @@ pub struct ADSR {
 impl ADSR {
     pub fn new() -> Self {
         let base_params = [0.0, 0.1, 0.1, 0.5, 0.1];
-
-        Self {
-            sample_rate: SAMPLE_RATE,
+        let mut env = Self {
+            sample_rate: sample_rate(),
             phase: 0,
             base_params,
-            tweek_env_by: Self::calc_tweek_by(base_params),
+            tweek_env_by: [0.0; 5],
             env: 0.0,
-        }
+        };
+        env.tweek_env_by = env.calc_tweek_by(base_params);
+
+        env
     }
 
-    fn calc_tweek_by(base_params: [f32; 5]) -> [f32; 5] {
+    fn calc_tweek_by(&self, base_params: [f32; 5]) -> [f32; 5] {
         let mut tweek_env_by = [0.0; 5];
 
-        tweek_env_by[ATTACK] = Self::calc_atk(base_params[ATTACK]);
-        tweek_env_by[DECAY] = Self::calc_decay(base_params[DECAY], base_params[SUSTAIN]);
-        tweek_env_by[RELEASE] = Self::calc_release(base_params[RELEASE], base_params[SUSTAIN]);
+        tweek_env_by[ATTACK] = self.calc_atk(base_params[ATTACK]);
+        tweek_env_by[DECAY] = self.calc_decay(base_params[DECAY], base_params[SUSTAIN]);
+        tweek_env_by[RELEASE] = self.calc_release(base_params[RELEASE], base_params[SUSTAIN]);
 
         // println!("{}", tweek_env_by[DECAY]);
 
         tweek_env_by
     }
 
-    fn calc_atk(atk_speed: f32) -> f32 {
-        1.0 / (atk_speed * SAMPLE_RATE as f32)
+    fn calc_atk(&self, atk_speed: f32) -> f32 {
+        1.0 / (atk_speed * self.sample_rate as f32)
     }
 
-    fn calc_decay(decay_speed: f32, sustain_level: f32) -> f32 {
-        (-1.0 + sustain_level) / (decay_speed * SAMPLE_RATE as f32)
+    fn calc_decay(&self, decay_speed: f32, sustain_level: f32) -> f32 {
+        (-1.0 + sustain_level) / (decay_speed * self.sample_rate as f32)
     }
 
-    fn calc_release(release_speed: f32, sustain_level: f32) -> f32 {
-        (-1.0 + sustain_level) / (release_speed * SAMPLE_RATE as f32)
+    fn calc_release(&self, release_speed: f32, sustain_level: f32) -> f32 {
+        (-1.0 + sustain_level) / (release_speed * self.sample_rate as f32)
     }
 
     pub fn set_atk(&mut self, atk: f32) {
         // set attack
         self.base_params[ATTACK] = atk.abs();
 
-        self.tweek_env_by[ATTACK] = Self::calc_atk(self.base_params[ATTACK]);
+        self.tweek_env_by[ATTACK] = self.calc_atk(self.base_params[ATTACK]);
     }
 
     pub fn set_decay(&mut self, decay: f32) {
@@ -65,7 +67,7 @@ impl ADSR {
         self.base_params[DECAY] = decay.abs();
 
         self.tweek_env_by[DECAY] =
-            Self::calc_decay(self.base_params[DECAY], self.base_params[SUSTAIN]);
+            self.calc_decay(self.base_params[DECAY], self.base_params[SUSTAIN]);
     }
 
     pub fn set_sus(&mut self, sustain: f32) {
@@ -73,16 +75,16 @@ impl ADSR {
         self.base_params[SUSTAIN] = sustain.abs();
 
         self.tweek_env_by[DECAY] =
-            Self::calc_decay(self.base_params[DECAY], self.base_params[SUSTAIN]);
+            self.calc_decay(self.base_params[DECAY], self.base_params[SUSTAIN]);
         self.tweek_env_by[RELEASE] =
-            Self::calc_release(self.base_params[RELEASE], self.base_params[SUSTAIN]);
+            self.calc_release(self.base_params[RELEASE], self.base_params[SUSTAIN]);
     }
 
     pub fn set_release(&mut self, release: f32) {
         let release = release.abs();
 
         self.base_params[RELEASE] = release;
-        self.tweek_env_by[RELEASE] = Self::calc_release(release, self.base_params[SUSTAIN]);
+        self.tweek_env_by[RELEASE] = self.calc_release(release, self.base_params[SUSTAIN]);
 
         // debug!("release: {release}, sustain: {}", self.base_params[SUSTAIN]);
         // debug!("release_tweak_by: {}", self.tweek_env_by[RELEASE]);
@@ -116,7 +118,7 @@ impl ADSR {
     pub fn release(&mut self) {
         self.phase = RELEASE;
         // self.env = self.base_params[SUSTAIN];
-        self.tweek_env_by[RELEASE] = Self::calc_release(self.base_params[RELEASE], 1.0 - self.env);
+        self.tweek_env_by[RELEASE] = self.calc_release(self.base_params[RELEASE], 1.0 - self.env);
 
         // info!("key released. base release time {}");
     }
@@ -1,4 +1,4 @@
-use crate::SAMPLE_RATE;
+use crate::sample_rate;
 
 pub const LFO_WAVE_TABLE_SIZE: usize = 128;
 
@@ -14,7 +14,7 @@ pub struct LFO {
 impl LFO {
     pub fn new() -> Self {
         Self {
-            sample_rate: SAMPLE_RATE,
+            sample_rate: sample_rate(),
             wave_table: Self::build_wave_table(),
             index: 0.0,
             index_increment: 0.0,
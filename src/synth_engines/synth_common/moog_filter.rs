@@ -1,4 +1,4 @@
-use crate::SAMPLE_RATE;
+use crate::sample_rate;
 // use num_traits::clamp;
 use std::f32::consts::PI;
 
@@ -32,7 +32,7 @@ impl HuovilainenMoog {
             res_quad: 0.0,
             coeff_cutoff: 0.0,
             coeff_resonance: 0.0,
-            sample_rate: SAMPLE_RATE as f32,
+            sample_rate: sample_rate() as f32,
         };
 
         filter.compute_coeffs(5_000.0, 0.75);
@@ -106,6 +106,12 @@ pub struct LowPass {
     pub cutoff: f32,
     pub resonance: f32,
     pub note: f32,
+    /// added to `cutoff` for the note that's playing, from its velocity.
+    pub brightness: f32,
+    /// added to `cutoff` from aftertouch.
+    pub pressure: f32,
+    /// added to `cutoff` from MPE timbre.
+    pub timbre: f32,
     // pub range: (f32, f32),
 }
 
@@ -119,6 +125,9 @@ impl LowPass {
             cutoff: 0.5,
             resonance: 0.5,
             note: 0.0,
+            brightness: 0.0,
+            pressure: 0.0,
+            timbre: 0.0,
             // range: (0.0, 0.0),
         }
     }
@@ -143,7 +152,8 @@ impl LowPass {
         //     self.note
         // };
         let delta = self.note * 12.0;
-        let nudge = delta * env * self.cutoff;
+        let nudge =
+            delta * env * (self.cutoff + self.brightness + self.pressure + self.timbre).min(1.0);
         let cutoff = (self.note) + nudge;
 
         self.filter.process(sample, cutoff, self.resonance * env)
@@ -1,5 +1,5 @@
 use super::{env::ADSR, moog_filter::LowPass, WaveTable, WAVE_TABLE_SIZE};
-use crate::SAMPLE_RATE;
+use crate::sample_rate;
 use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug)]
@@ -20,7 +20,7 @@ pub struct WavetableOscillator {
 impl WavetableOscillator {
     pub fn new() -> Self {
         Self {
-            sample_rate: SAMPLE_RATE as f32,
+            sample_rate: sample_rate() as f32,
             index: 0.0,
             index_increment: 0.0,
         }
@@ -160,9 +160,10 @@ impl Oscillator {
         self.wt_osc.set_frequency(self.frequency + adjust_amt)
     }
 
-    pub fn bend(&mut self, bend: f32) {
+    /// bends the note `semitones` up, or down if negative.
+    pub fn bend(&mut self, semitones: f32) {
         // println!("bending");
-        let new_freq = self.base_frequency * 2.0_f32.powf((bend * 3.0) / 12.0);
+        let new_freq = self.base_frequency * 2.0_f32.powf(semitones / 12.0);
         // + self.frequency;
         self.wt_osc.set_frequency(new_freq);
         // println!("frequency => {}", self.frequency);
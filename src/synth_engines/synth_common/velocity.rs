@@ -0,0 +1,58 @@
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// how a note's velocity is turned into how hard it was played.
+#[cfg_attr(
+    feature = "pyo3",
+    pyclass(module = "stepper_synth_backend", get_all, eq, eq_int, hash, frozen)
+)]
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    /// gentle playing comes out stronger.
+    Soft,
+    /// it takes more force to get loud.
+    Hard,
+    /// every note is played as hard as it can be.
+    Fixed,
+}
+
+/// how an engine responds to velocity. by default it does nothing, so patches sound as they did
+/// before velocity was read.
+#[cfg_attr(feature = "pyo3", pyclass(module = "stepper_synth_backend", get_all))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Velocity {
+    pub curve: VelocityCurve,
+    /// how much velocity changes the volume. at 0.0 every note is full volume, at 1.0 the softest
+    /// notes are silent.
+    pub amp: f32,
+    /// how much velocity opens the filter, on engines with one.
+    pub cutoff: f32,
+}
+
+impl Velocity {
+    /// `velocity` through the curve, from 0.0 to 1.0.
+    pub fn strength(&self, velocity: u8) -> f32 {
+        let velocity = velocity.min(127) as f32 / 127.0;
+
+        match self.curve {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => velocity.sqrt(),
+            VelocityCurve::Hard => velocity * velocity,
+            VelocityCurve::Fixed => 1.0,
+        }
+    }
+
+    /// the gain of a note played at `velocity`.
+    pub fn gain(&self, velocity: u8) -> f32 {
+        1.0 - self.amp.clamp(0.0, 1.0) * (1.0 - self.strength(velocity))
+    }
+
+    /// how much further than its cutoff the filter opens for a note played at `velocity`.
+    pub fn brightness(&self, velocity: u8) -> f32 {
+        self.cutoff * self.strength(velocity)
+    }
+}
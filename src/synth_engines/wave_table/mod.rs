@@ -1,19 +1,115 @@
 use crate::{
-    pygame_coms::{GuiParam, Knob},
-    HashMap, KnobCtrl, SampleGen,
+    pygame_coms::{GuiParam, Knob, WTSynthParam},
+    sample_rate, HashMap, KnobCtrl, SampleGen,
 };
-use log::warn;
-use midi_control::MidiNote;
+use anyhow::{bail, Result};
+use log::*;
+use midi_control::{Channel, ControlEvent, MidiMessage, MidiNote};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 // #[cfg(feature = "pyo3")]
 // use pyo3::prelude::*;
-use wavetable_synth::{App, SampleGen as _};
+use wavetable_synth::{
+    common::{LowPass, LowPassParam, ModMatrixDest, ModMatrixItem, ModMatrixSrc, OscParam},
+    synth_engines::synth_common::env::{ATTACK, DECAY, RELEASE, SUSTAIN},
+    App, MidiControlled as _, SampleGen as _,
+};
+
+use super::{mpe::TIMBRE_CC, LfoInput, SynthEngine, BEND_CENTER};
+
+/// how far wavetable-synth bends at either end of the pitch wheel, in semitones.
+const WT_BEND_RANGE: f32 = 2.0;
+/// the rate wavetable-synth renders at. it keeps its own, so its output is resampled to ours.
+const WT_SAMPLE_RATE: u32 = 48_000;
+
+/// a mod matrix entry with aftertouch as its source. wavetable-synth has no aftertouch source so
+/// the engine applies these itself, moving the destination by the mod's offset while it renders
+/// and putting it back after, so the destination's own value is never touched.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AftertouchMod {
+    pub dest: ModMatrixDest,
+    pub amt: f32,
+    pub bipolar: bool,
+}
+
+impl AftertouchMod {
+    /// how far the destination is moved at `pressure`.
+    fn offset(&self, pressure: f32) -> f32 {
+        let pressure = if self.bipolar {
+            pressure * 2.0 - 1.0
+        } else {
+            pressure
+        };
+
+        pressure * self.amt
+    }
+}
+
+/// everything `set_param` can change, so the engine can be saved with a `Preset`. wavetable-synth
+/// keeps its patch spread over the voices, each voice is set the same so it's read from the
+/// first. the wave shapes and osc targets can't be changed yet, so they aren't saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveTablePatch {
+    pub oscs: Vec<OscPatch>,
+    pub filters: Vec<LowPassPatch>,
+    pub envs: Vec<ADSRPatch>,
+    /// each LFO's speed, in seconds per cycle like `WTSynthParam::LfoSpeed`.
+    pub lfos: Vec<f32>,
+    pub mod_matrix: Vec<ModPatch>,
+    #[serde(default)]
+    pub aftertouch_mods: Vec<AftertouchMod>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OscPatch {
+    pub on: bool,
+    pub level: f32,
+    pub detune: f32,
+    pub offset: i16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LowPassPatch {
+    pub cutoff: f32,
+    pub res: f32,
+    pub mix: f32,
+    pub key_track: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ADSRPatch {
+    pub atk: f32,
+    pub dcy: f32,
+    pub sus: f32,
+    pub rel: f32,
+}
 
-use super::{LfoInput, SynthEngine};
+/// a wavetable-synth mod matrix entry.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModPatch {
+    pub src: ModMatrixSrc,
+    pub dest: ModMatrixDest,
+    pub amt: f32,
+    pub bipolar: bool,
+}
 
 #[derive(Debug, Clone)]
 pub struct WaveTableEngine {
     pub synth: App,
+    /// listed after the wavetable-synth mod matrix, with ids carrying on from its entries.
+    pub aftertouch_mods: Vec<AftertouchMod>,
+    /// the last channel aftertouch, from 0.0 to 1.0.
+    pressure: f32,
+    /// the destinations' own values, one per mod per voice, kept while a block renders.
+    base: Vec<f32>,
     lfo_target: LfoInput,
+    /// how far through wavetable-synth's samples each of ours moves, 1.0 when the rates match.
+    step: f32,
+    /// where between `last` and `next` the output is, from 0.0 to 1.0.
+    phase: f32,
+    /// the two wavetable-synth samples the output is read between.
+    last: f32,
+    next: f32,
 }
 
 impl WaveTableEngine {
@@ -31,14 +127,519 @@ impl WaveTableEngine {
 
         Self {
             synth,
+            aftertouch_mods: Vec::new(),
+            pressure: 0.0,
+            base: Vec::new(),
             lfo_target: LfoInput::default(),
+            step: WT_SAMPLE_RATE as f32 / sample_rate() as f32,
+            phase: 0.0,
+            last: 0.0,
+            next: 0.0,
+        }
+    }
+
+    /// how many entries the wavetable-synth mod matrix has, the first aftertouch mod's id.
+    pub fn mod_matrix_len(&self) -> usize {
+        self.synth
+            .mod_matrix
+            .iter()
+            .filter(|item| item.is_some())
+            .count()
+    }
+
+    /// runs `f` on `dest` in every voice. returns false if aftertouch can't move `dest`.
+    fn for_dest(synth: &App, dest: ModMatrixDest, mut f: impl FnMut(&mut f32)) -> bool {
+        for voice in synth.voices.iter() {
+            let mut voice = voice.lock().unwrap();
+
+            let value = match dest {
+                ModMatrixDest::Osc { osc, param } => {
+                    let Some((osc, _on)) = voice.oscs.get_mut(osc) else {
+                        return false;
+                    };
+
+                    match param {
+                        OscParam::Level => &mut osc.level,
+                        OscParam::Tune => &mut osc.detune,
+                    }
+                }
+                ModMatrixDest::LowPass { low_pass, param } => {
+                    let filter = match low_pass {
+                        LowPass::LP1 => &mut voice.filters[0],
+                        LowPass::LP2 => &mut voice.filters[1],
+                    };
+
+                    match param {
+                        LowPassParam::Cutoff => &mut filter.cutoff,
+                        LowPassParam::Res => &mut filter.resonance,
+                        LowPassParam::Mix => &mut filter.mix,
+                    }
+                }
+                _ => return false,
+            };
+
+            f(value);
+        }
+
+        true
+    }
+
+    /// puts `item` in the first free slot of the wavetable-synth mod matrix.
+    fn add_mod(&mut self, item: ModMatrixItem) {
+        let Some(slot) = self.synth.mod_matrix.iter_mut().find(|slot| slot.is_none()) else {
+            warn!("the mod matrix is full, dropping {item:?}");
+            return;
+        };
+
+        info!("adding matrix item {item:?} to the mod_matrix");
+        *slot = Some(item);
+    }
+
+    fn add_aftertouch_mod(&mut self, dest: ModMatrixDest, amt: f32, bipolar: bool) {
+        if !Self::for_dest(&self.synth, dest, |_| {}) {
+            error!("aftertouch can't modulate {dest:?}");
+            return;
+        }
+
+        let item = AftertouchMod { dest, amt, bipolar };
+        info!("adding aftertouch mod {item:?}");
+        self.aftertouch_mods.push(item);
+    }
+
+    /// removes aftertouch mod `i`.
+    fn remove_aftertouch_mod(&mut self, i: usize) {
+        if i >= self.aftertouch_mods.len() {
+            warn!("there is no aftertouch mod {i} to remove");
+            return;
+        }
+
+        self.aftertouch_mods.remove(i);
+    }
+
+    /// adds the aftertouch mods on top of their destinations, saving the values they move in
+    /// `base`. it's done once a block since every voice has to be locked to do it.
+    fn apply_aftertouch(&mut self) {
+        self.base.clear();
+
+        for item in self.aftertouch_mods.iter() {
+            let offset = item.offset(self.pressure);
+
+            Self::for_dest(&self.synth, item.dest, |value| {
+                self.base.push(*value);
+                *value += offset;
+            });
+        }
+    }
+
+    /// puts back exactly what `apply_aftertouch` moved, so GUI edits and the patch never see the
+    /// pressure.
+    fn restore_aftertouch(&mut self) {
+        let n_voices = self.synth.voices.len();
+        let mut end = self.base.len();
+
+        // in reverse, so a destination moved by two mods ends up at its first saved value.
+        for item in self.aftertouch_mods.iter().rev() {
+            let mut i = end - n_voices;
+            end = i;
+
+            Self::for_dest(&self.synth, item.dest, |value| {
+                *value = self.base[i];
+                i += 1;
+            });
+        }
+    }
+
+    /// the next wavetable-synth sample, resampled to our rate.
+    fn next_sample(&mut self) -> f32 {
+        if self.step == 1.0 {
+            return self.synth.get_sample();
+        }
+
+        self.phase += self.step;
+
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.last = self.next;
+            self.next = self.synth.get_sample();
+        }
+
+        self.last + (self.next - self.last) * self.phase
+    }
+
+    pub fn patch(&self) -> WaveTablePatch {
+        let voice = self.synth.voices[0].lock().unwrap();
+
+        WaveTablePatch {
+            oscs: voice
+                .oscs
+                .iter()
+                .map(|(osc, on)| OscPatch {
+                    on: *on,
+                    level: osc.level,
+                    detune: osc.detune,
+                    offset: osc.offset,
+                })
+                .collect(),
+            filters: voice
+                .filters
+                .iter()
+                .map(|filter| LowPassPatch {
+                    cutoff: filter.cutoff,
+                    res: filter.resonance,
+                    mix: filter.mix,
+                    key_track: filter.key_track,
+                })
+                .collect(),
+            envs: voice
+                .envs
+                .iter()
+                .map(|env| ADSRPatch {
+                    atk: env.base_params[ATTACK],
+                    dcy: env.base_params[DECAY],
+                    sus: env.base_params[SUSTAIN],
+                    rel: env.base_params[RELEASE],
+                })
+                .collect(),
+            lfos: voice.lfos.iter().map(|lfo| 1.0 / lfo.freq).collect(),
+            mod_matrix: self
+                .synth
+                .mod_matrix
+                .iter()
+                .flatten()
+                .map(|item| ModPatch {
+                    src: item.src,
+                    dest: item.dest,
+                    amt: item.amt,
+                    bipolar: item.bipolar,
+                })
+                .collect(),
+            aftertouch_mods: self.aftertouch_mods.clone(),
+        }
+    }
+
+    /// sets every voice and the mod matrix to `patch`. anything `patch` has more of than the
+    /// engine is dropped.
+    pub fn load_patch(&mut self, patch: &WaveTablePatch) {
+        for voice in self.synth.voices.iter() {
+            let mut voice = voice.lock().unwrap();
+
+            for ((osc, on), saved) in voice.oscs.iter_mut().zip(patch.oscs.iter()) {
+                *on = saved.on;
+                osc.level = saved.level;
+                osc.detune = saved.detune;
+                osc.offset = saved.offset;
+            }
+
+            for (filter, saved) in voice.filters.iter_mut().zip(patch.filters.iter()) {
+                filter.cutoff = saved.cutoff;
+                filter.resonance = saved.res;
+                filter.mix = saved.mix;
+                filter.key_track = saved.key_track;
+            }
+
+            for (env, saved) in voice.envs.iter_mut().zip(patch.envs.iter()) {
+                env.set_atk(saved.atk);
+                env.set_decay(saved.dcy);
+                env.set_sus(saved.sus);
+                env.set_release(saved.rel);
+            }
+
+            for (lfo, speed) in voice.lfos.iter_mut().zip(patch.lfos.iter()) {
+                lfo.set_frequency(1.0 / speed);
+            }
+        }
+
+        let mut saved = patch.mod_matrix.iter();
+
+        for item in self.synth.mod_matrix.iter_mut() {
+            *item = saved.next().map(|saved| ModMatrixItem {
+                src: saved.src,
+                dest: saved.dest,
+                amt: saved.amt,
+                bipolar: saved.bipolar,
+            });
+        }
+
+        if saved.next().is_some() {
+            warn!("the mod matrix is full, dropping the rest of the patch's entries");
+        }
+
+        // mods aftertouch can't move are dropped, `restore_aftertouch` counts on every mod moving
+        // each voice.
+        self.aftertouch_mods.clear();
+        self.aftertouch_mods.extend(
+            patch
+                .aftertouch_mods
+                .iter()
+                .filter(|item| Self::for_dest(&self.synth, item.dest, |_| {})),
+        );
+    }
+
+    /// `App` keeps its voices behind `Arc`s so a clone still plays through the original's voices.
+    /// this gives a clone voices of its own.
+    pub fn detach(&mut self) {
+        for voice in self.synth.voices.iter_mut() {
+            let copy = voice.lock().unwrap().clone();
+            *voice = Arc::new(Mutex::new(copy));
+        }
+    }
+
+    pub fn set_param(&mut self, param: WTSynthParam) {
+        match param {
+            WTSynthParam::OscOn { n, on } => set_voices(&self.synth.voices, "osc", n, |v| {
+                v.oscs.get_mut(n)?.1 = on;
+                Some(())
+            }),
+            WTSynthParam::OscVol { n, to } => set_voices(&self.synth.voices, "osc", n, |v| {
+                v.oscs.get_mut(n)?.0.level = to;
+                Some(())
+            }),
+            WTSynthParam::OscDetune { n, detune } => {
+                set_voices(&self.synth.voices, "osc", n, |v| {
+                    v.oscs.get_mut(n)?.0.detune = detune;
+                    Some(())
+                })
+            }
+            WTSynthParam::OscWaveTable {
+                n: _,
+                wave_table: _,
+            } => {
+                // self
+                //     .synth
+                //     .voices
+                //     .iter()
+                //     .for_each(|v| v.lock().unwrap().oscs[n].0.wave_table = wave_table);
+                // TODO: make happen
+            }
+            WTSynthParam::OscOffset { n, offset } => {
+                set_voices(&self.synth.voices, "osc", n, |v| {
+                    v.oscs.get_mut(n)?.0.offset = offset;
+                    Some(())
+                })
+            }
+            WTSynthParam::OscTarget { n: _, target: _ } => {
+                // self
+                // .synth
+                // .voices
+                // .iter()
+                // .for_each(|v| v.lock().unwrap().oscs[n].0.target += target);
+                // TODO: make happen
+            }
+            WTSynthParam::LowPassCutoff { n, cutoff } => {
+                set_voices(&self.synth.voices, "low pass", n, |v| {
+                    v.filters.get_mut(n)?.cutoff = cutoff;
+                    Some(())
+                })
+            }
+            WTSynthParam::LowPassRes { n, res } => {
+                set_voices(&self.synth.voices, "low pass", n, |v| {
+                    v.filters.get_mut(n)?.resonance = res;
+                    Some(())
+                })
+            }
+            WTSynthParam::LowPassMix { n, mix } => {
+                set_voices(&self.synth.voices, "low pass", n, |v| {
+                    v.filters.get_mut(n)?.mix = mix;
+                    Some(())
+                })
+            }
+            WTSynthParam::LowPassTracking { n, track } => {
+                set_voices(&self.synth.voices, "low pass", n, |v| {
+                    v.filters.get_mut(n)?.key_track = track;
+                    Some(())
+                })
+            }
+            WTSynthParam::ADSRAttack { n, val } => {
+                set_voices(&self.synth.voices, "envelope", n, |v| {
+                    v.envs.get_mut(n)?.set_atk(val);
+                    Some(())
+                })
+            }
+            WTSynthParam::ADSRDecay { n, val } => {
+                set_voices(&self.synth.voices, "envelope", n, |v| {
+                    v.envs.get_mut(n)?.set_decay(val);
+                    Some(())
+                })
+            }
+            WTSynthParam::ADSRSustain { n, val } => {
+                set_voices(&self.synth.voices, "envelope", n, |v| {
+                    v.envs.get_mut(n)?.set_sus(val);
+                    Some(())
+                })
+            }
+            WTSynthParam::ADSRRelease { n, val } => {
+                set_voices(&self.synth.voices, "envelope", n, |v| {
+                    v.envs.get_mut(n)?.set_release(val);
+                    Some(())
+                })
+            }
+            WTSynthParam::LfoSpeed { n, speed } => set_voices(&self.synth.voices, "LFO", n, |v| {
+                v.lfos.get_mut(n)?.set_frequency(1.0 / speed);
+                Some(())
+            }),
+            WTSynthParam::ModMatrixAdd {
+                src,
+                dest,
+                amt,
+                bipolar,
+            } => {
+                let Ok(dest) = str_to_mod_dest(&dest) else {
+                    error!("the destination {dest:?} failed to convert to ModMatrixDest");
+                    return;
+                };
+
+                if is_aftertouch_src(&src) {
+                    self.add_aftertouch_mod(dest, amt, bipolar);
+                    return;
+                }
+
+                let Ok(src) = str_to_mod_src(&src) else {
+                    error!("the source {src:?} failed to convert to ModMatrixSrc");
+                    return;
+                };
+                // let s = ModMatrixSrc::Velocity;
+                // warn!("{:?}", toml::to_string_pretty(&s));
+
+                // let Ok(src) = toml::from_str::<ModMatrixSrc>(&src) else {
+                //     error!("the source {src:?} failed to convert to ModMatrixSrc");
+                //     return;
+                // };
+                // info!("src => {src:?}");
+
+                // let d = ModMatrixDest::SynthVolume;
+                // warn!("{:?}", toml::to_string_pretty(&d));
+
+                // info!("dest => {dest:?}");
+                self.add_mod(ModMatrixItem {
+                    src,
+                    dest,
+                    amt,
+                    bipolar,
+                });
+            }
+            WTSynthParam::ModMatrixDel { id } if id >= self.mod_matrix_len() => {
+                self.remove_aftertouch_mod(id - self.mod_matrix_len());
+            }
+            WTSynthParam::ModMatrixDel { id } => {
+                let mut to_rm = [id].to_vec();
+
+                loop {
+                    let Some(id) = to_rm.pop() else {
+                        break;
+                    };
+
+                    let matrix = self.synth.mod_matrix.clone();
+
+                    // rm the identified matrix entry & scootch everything after it down.
+                    for i in (id + 1)..matrix.len() {
+                        self.synth.mod_matrix[i - 1] = matrix[i];
+                    }
+
+                    // if any matrix entries modulate the amount of a matrix entry with an id GREATER
+                    // then the rm'ed id adjust to account for the shift from the above for loop.
+                    //
+                    // if any matrix entries modulate the amount of a matrix entry with an id EQUAL to
+                    // that of the rm'ed id rm them too.
+                    for (i, item) in self.synth.mod_matrix.iter_mut().enumerate() {
+                        if let Some(ref mut entry) = item {
+                            match entry.dest {
+                                ModMatrixDest::ModMatrixEntryModAmt(ref mut n) => {
+                                    if *n > id {
+                                        *n -= 1
+                                    } else if *n == id {
+                                        // // recurse
+                                        // self.wt_param_setter(WTSynthParam::ModMatrixDel { id: i })
+                                        to_rm.push(i);
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+            WTSynthParam::ModMatrixMod {
+                id,
+                src,
+                dest,
+                amt,
+                bipolar,
+            } => {
+                let len = self.mod_matrix_len();
+                // ids here count from 1, the aftertouch mods carry on after the matrix entries.
+                let Some(i) = id
+                    .checked_sub(1)
+                    .filter(|i| *i < len + self.aftertouch_mods.len())
+                else {
+                    warn!("there is no mod matrix entry {id} to change");
+                    return;
+                };
+                let Ok(dest) = str_to_mod_dest(&dest) else {
+                    error!("the destination {dest:?} failed to convert to ModMatrixDest");
+                    return;
+                };
+
+                if is_aftertouch_src(&src) {
+                    if !Self::for_dest(&self.synth, dest, |_| {}) {
+                        error!("aftertouch can't modulate {dest:?}");
+                        return;
+                    }
+
+                    let item = AftertouchMod { dest, amt, bipolar };
+
+                    if i >= len {
+                        self.aftertouch_mods[i - len] = item;
+                    } else {
+                        // it moves out of the wavetable-synth mod matrix.
+                        self.set_param(WTSynthParam::ModMatrixDel { id: i });
+                        self.aftertouch_mods.push(item);
+                    }
+
+                    return;
+                }
+
+                let Ok(src) = str_to_mod_src(&src) else {
+                    error!("the source {src:?} failed to convert to ModMatrixSrc");
+                    return;
+                };
+                // let Ok(src) = toml::from_str::<ModMatrixSrc>(&src) else {
+                //     error!("the source {src:?} failed to convert to ModMatrixSrc");
+                //     return;
+                // };
+                let matrix_item = ModMatrixItem {
+                    src,
+                    dest,
+                    amt,
+                    bipolar,
+                };
+
+                if i >= len {
+                    // it moves into the wavetable-synth mod matrix.
+                    self.remove_aftertouch_mod(i - len);
+                    self.add_mod(matrix_item);
+                } else {
+                    self.synth.mod_matrix[i] = Some(matrix_item);
+                }
+            }
+            _ => {}
         }
     }
 }
 
 impl SampleGen for WaveTableEngine {
     fn get_sample(&mut self) -> f32 {
-        self.synth.get_sample()
+        let mut sample = [0.0];
+        self.get_block(&mut sample);
+
+        sample[0]
+    }
+
+    fn get_block(&mut self, buffer: &mut [f32]) {
+        self.apply_aftertouch();
+        buffer
+            .iter_mut()
+            .for_each(|sample| *sample = self.next_sample());
+        self.restore_aftertouch();
     }
 }
 
@@ -63,12 +664,37 @@ impl SynthEngine for WaveTableEngine {
         self.synth.stop(note);
     }
 
-    fn bend(&mut self, _amount: f32) {
-        warn!("bending not yet implemented for WaveTableEngine")
+    fn bend(&mut self, semitones: f32) {
+        // wavetable-synth reads the pitch wheel itself, so the bend is sent back as one scaled to
+        // its range.
+        let amount = (semitones / WT_BEND_RANGE).clamp(-1.0, 1.0);
+        let value = if amount >= 0.0 {
+            BEND_CENTER as f32 + amount * (0x3fff - BEND_CENTER) as f32
+        } else {
+            BEND_CENTER as f32 + amount * BEND_CENTER as f32
+        }
+        .round() as u16;
+
+        self.synth.midi_input(&MidiMessage::PitchBend(
+            Channel::Ch1,
+            (value & 0x7f) as u8,
+            (value >> 7) as u8,
+        ));
+    }
+
+    fn aftertouch(&mut self, pressure: f32) {
+        self.pressure = pressure;
     }
 
-    fn unbend(&mut self) {
-        warn!("bending not yet implemented for WaveTableEngine")
+    fn note_timbre(&mut self, _note: MidiNote, timbre: f32) {
+        // wavetable-synth has no per voice timbre, it gets the CC like any other.
+        self.synth.midi_input(&MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlEvent {
+                control: TIMBRE_CC,
+                value: (timbre * 127.0).round() as u8,
+            },
+        ));
     }
 
     fn volume_swell(&mut self, _amount: f32) -> bool {
@@ -86,3 +712,146 @@ impl SynthEngine for WaveTableEngine {
         map
     }
 }
+
+/// runs `set` on every voice. `set` gives `None` when there's no `what` number `n`, then the
+/// command is dropped, every voice has the same number of each so none were changed.
+fn set_voices<V>(
+    voices: &[Arc<Mutex<V>>],
+    what: &str,
+    n: usize,
+    set: impl Fn(&mut V) -> Option<()>,
+) {
+    for voice in voices {
+        if set(&mut voice.lock().unwrap()).is_none() {
+            warn!("dropping a wave table change, there is no {what} {n}");
+            return;
+        }
+    }
+}
+
+/// true if `src` names aftertouch, which is routed by this engine instead of wavetable-synth.
+fn is_aftertouch_src(src: &str) -> bool {
+    matches!(
+        src.trim().to_lowercase().as_str(),
+        "aftertouch" | "after-touch" | "pressure" | "at"
+    )
+}
+
+fn str_to_mod_src(src: &str) -> Result<ModMatrixSrc> {
+    let src = src.trim().to_lowercase();
+
+    if src.starts_with("env-") {
+        let n: usize = src.split("-").collect::<Vec<_>>()[1].parse()?;
+
+        return Ok(ModMatrixSrc::Env(n));
+    }
+
+    if src.starts_with("lfo-") {
+        let n: usize = src.split("-").collect::<Vec<_>>()[1].parse()?;
+
+        return Ok(ModMatrixSrc::Lfo(n));
+    }
+
+    Ok(match src.as_str() {
+        "velocity" | "vel" => ModMatrixSrc::Velocity,
+        "gate" => ModMatrixSrc::Gate,
+        "mod-wheel" | "mod-whl" => ModMatrixSrc::ModWheel,
+        "pitch-wheel" | "pitch-whl" => ModMatrixSrc::PitchWheel,
+        "macro-1" | "macro1" | "m-1" | "m1" => ModMatrixSrc::Macro1,
+        "macro-2" | "macro2" | "m-2" | "m2" => ModMatrixSrc::Macro1,
+        "macro-3" | "macro3" | "m-3" | "m3" => ModMatrixSrc::Macro1,
+        "macro-4" | "macro4" | "m-4" | "m4" => ModMatrixSrc::Macro1,
+        // "" => ModMatrixSrc::,
+        _ => bail!(""),
+    })
+}
+
+fn str_to_mod_dest(dest: &str) -> Result<ModMatrixDest> {
+    if dest.to_lowercase().starts_with("vol") {
+        return Ok(ModMatrixDest::SynthVolume);
+    }
+
+    Ok(toml::from_str::<ModMatrixDest>(dest)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::set_sample_rate;
+
+    #[test]
+    fn aftertouch_leaves_the_patch_alone() {
+        set_sample_rate(44_100);
+        let mut wt = WaveTableEngine::new();
+        wt.aftertouch_mods = vec![
+            AftertouchMod {
+                dest: ModMatrixDest::Osc {
+                    osc: 0,
+                    param: OscParam::Level,
+                },
+                amt: 0.25,
+                bipolar: false,
+            };
+            2
+        ];
+        let patch = wt.patch();
+
+        wt.aftertouch(1.0);
+        wt.get_block(&mut [0.0; 64]);
+        wt.get_sample();
+
+        assert_eq!(wt.patch(), patch);
+    }
+
+    #[test]
+    fn aftertouch_entries_can_be_changed() {
+        set_sample_rate(48_000);
+        let mut wt = WaveTableEngine::new();
+        let osc_level = "[Osc]\nosc = 1\nparam = \"Level\"";
+        let cutoff = "[LowPass]\nlow_pass = \"LP1\"\nparam = \"Cutoff\"";
+        let change = |id: usize, src: &str, dest: &str| WTSynthParam::ModMatrixMod {
+            id,
+            src: src.into(),
+            dest: dest.into(),
+            amt: 0.5,
+            bipolar: true,
+        };
+
+        wt.set_param(WTSynthParam::ModMatrixAdd {
+            src: "vel".into(),
+            dest: "vol".into(),
+            amt: 1.0,
+            bipolar: false,
+        });
+        wt.set_param(WTSynthParam::ModMatrixAdd {
+            src: "aftertouch".into(),
+            dest: osc_level.into(),
+            amt: 1.0,
+            bipolar: false,
+        });
+        assert_eq!((wt.mod_matrix_len(), wt.aftertouch_mods.len()), (1, 1));
+
+        // the aftertouch mod is listed second.
+        wt.set_param(change(2, "aftertouch", cutoff));
+        assert_eq!(
+            wt.aftertouch_mods,
+            [AftertouchMod {
+                dest: ModMatrixDest::LowPass {
+                    low_pass: LowPass::LP1,
+                    param: LowPassParam::Cutoff,
+                },
+                amt: 0.5,
+                bipolar: true,
+            }]
+        );
+
+        // a new source moves an entry between the mod matrix and the aftertouch mods.
+        wt.set_param(change(2, "lfo-1", cutoff));
+        assert_eq!((wt.mod_matrix_len(), wt.aftertouch_mods.len()), (2, 0));
+        assert_eq!(wt.synth.mod_matrix[1].unwrap().src, ModMatrixSrc::Lfo(1));
+
+        wt.set_param(change(1, "aftertouch", osc_level));
+        assert_eq!((wt.mod_matrix_len(), wt.aftertouch_mods.len()), (1, 1));
+        assert_eq!(wt.synth.mod_matrix[0].unwrap().src, ModMatrixSrc::Lfo(1));
+    }
+}
@@ -57,10 +57,22 @@ impl WurlitzerEngine {
             .for_each(|osc| osc.set_trem_depth(depth))
     }
 
-    pub fn bend_all(&mut self, bend: f32) {
+    /// the voices playing `note`, or every playing voice if `None`.
+    fn voices(&mut self, note: Option<MidiNote>) -> impl Iterator<Item = &mut WurliNoteOsc> + '_ {
+        self.osc_s.iter_mut().filter(move |osc| {
+            osc.playing.is_some() && note.is_none_or(|note| osc.playing == Some(note))
+        })
+    }
+
+    /// sets the aftertouch of `note`, or of every note if `None`.
+    pub fn set_pressure(&mut self, note: Option<MidiNote>, pressure: f32) {
+        self.voices(note).for_each(|osc| osc.pressure = pressure)
+    }
+
+    pub fn bend_all(&mut self, semitones: f32) {
         for osc in self.osc_s.iter_mut() {
             if osc.playing.is_some() {
-                osc.bend(bend);
+                osc.bend(semitones);
             }
         }
     }
@@ -91,6 +103,18 @@ impl SampleGen for WurlitzerEngine {
         // sample * 0.75
         (sample * 0.5).tanh()
     }
+
+    fn get_block(&mut self, buffer: &mut [f32]) {
+        if !self.is_active() {
+            buffer.fill(0.0);
+
+            return;
+        }
+
+        for sample in buffer.iter_mut() {
+            *sample = self.get_sample();
+        }
+    }
 }
 
 impl KnobCtrl for WurlitzerEngine {
@@ -109,6 +133,12 @@ impl SynthEngine for WurlitzerEngine {
         "Wurlitzer".into()
     }
 
+    fn is_active(&self) -> bool {
+        self.osc_s
+            .iter()
+            .any(|osc| osc.playing.is_some() || osc.vol_env.pressed())
+    }
+
     fn play(&mut self, note: MidiNote, velocity: u8) {
         self.play(note, velocity);
     }
@@ -117,14 +147,30 @@ impl SynthEngine for WurlitzerEngine {
         self.stop(note);
     }
 
-    fn bend(&mut self, amount: f32) {
-        self.bend_all(amount);
+    fn bend(&mut self, semitones: f32) {
+        self.bend_all(semitones);
     }
 
     fn unbend(&mut self) {
         self.unbend();
     }
 
+    fn aftertouch(&mut self, pressure: f32) {
+        self.set_pressure(None, pressure);
+    }
+
+    fn poly_aftertouch(&mut self, note: MidiNote, pressure: f32) {
+        self.set_pressure(Some(note), pressure);
+    }
+
+    fn note_bend(&mut self, note: MidiNote, semitones: f32) {
+        self.voices(Some(note)).for_each(|osc| osc.bend(semitones))
+    }
+
+    fn note_timbre(&mut self, note: MidiNote, timbre: f32) {
+        self.voices(Some(note)).for_each(|osc| osc.timbre = timbre)
+    }
+
     fn volume_swell(&mut self, _amount: f32) -> bool {
         false
     }
@@ -13,6 +13,8 @@ const WURLI_ENV_REL: f32 = 0.01;
 const KEY_FRAME_MOD: f32 = 0.68;
 const FORMANT_SHIFTING: f32 = 0.11;
 const TREM_DEPTH_MOD_AMT: f32 = 0.585;
+/// how much of the harmonic full MPE timbre mixes in.
+const TIMBRE_MIX: f32 = 0.25;
 
 // #[derive(Debug, Clone)]
 // pub struct ApLowPass {}
@@ -48,6 +50,10 @@ pub struct WurliNoteOsc {
     formant: WavetableOscillator,
     trem_lfo: LFO,
     pub trem_lfo_depth: f32,
+    /// aftertouch, from 0.0 to 1.0, deepens the tremolo.
+    pub pressure: f32,
+    /// MPE timbre, from 0.0 to 1.0, brings up the harmonic.
+    pub timbre: f32,
     // comb_filter
     pub vol_env: ADSR,
     param_env: ADSR,
@@ -122,6 +128,8 @@ impl WurliNoteOsc {
             osc_2,
             trem_lfo,
             trem_lfo_depth: 0.5,
+            pressure: 0.0,
+            timbre: 0.0,
             vol_env,
             param_env,
             formant,
@@ -138,6 +146,8 @@ impl WurliNoteOsc {
     pub fn press(&mut self, midi_note: u8, vel: f32) {
         // info!("playing note: {midi_note} with vel: {vel}");
         self.vel = vel;
+        self.pressure = 0.0;
+        self.timbre = 0.0;
         self.vol_env.press();
         self.param_env.press();
         self.frequency = Self::get_freq(midi_note);
@@ -166,16 +176,10 @@ impl WurliNoteOsc {
         // self.playing = None;
     }
 
-    pub fn bend(&mut self, bend: f32) {
+    /// bends the note `semitones` up, or down if negative.
+    pub fn bend(&mut self, semitones: f32) {
         // println!("bending");
-        let nudge = 2.0_f32.powf((bend * 3.0).abs() / 12.0);
-        let new_freq = if bend < 0.0 {
-            self.base_frequency / nudge
-        } else if bend > 0.0 {
-            self.base_frequency * nudge
-        } else {
-            self.base_frequency
-        };
+        let new_freq = self.base_frequency * 2.0_f32.powf(semitones / 12.0);
         // + self.frequency;
         self.osc_1.set_frequency(new_freq);
         self.osc_2.set_frequency(new_freq);
@@ -197,9 +201,10 @@ impl SampleGen for WurliNoteOsc {
         let mut harmonic_1 = self.osc_2.get_sample();
         let mut fundimental = self.osc_1.get_sample();
         let p_env = self.param_env.get_samnple();
-        let mix = p_env * KEY_FRAME_MOD * self.vel;
+        let mix = p_env * KEY_FRAME_MOD * self.vel + self.timbre * TIMBRE_MIX;
         // let mix = p_env * KEY_FRAME_MOD * self.vel;
-        let trem_lfo = self.trem_lfo.get_sample() * self.trem_lfo_depth * TREM_DEPTH_MOD_AMT;
+        let trem_depth = (self.trem_lfo_depth + self.pressure).min(1.0);
+        let trem_lfo = self.trem_lfo.get_sample() * trem_depth * TREM_DEPTH_MOD_AMT;
         let mut vol = self.vol_env.get_samnple();
 
         if vol == 0.0 {
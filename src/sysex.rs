@@ -0,0 +1,373 @@
+use crate::session::{Preset, Session};
+use anyhow::{bail, ensure, Result};
+use log::*;
+use std::time::Duration;
+
+pub const SYSEX_START: u8 = 0xF0;
+pub const SYSEX_END: u8 = 0xF7;
+/// the manufacturer ID set aside for non-commercial use.
+const MANUFACTURER_ID: u8 = 0x7D;
+/// tells our dumps apart from anyone else's non-commercial SysEx.
+const DEVICE_TAG: [u8; 2] = *b"SS";
+/// bumped whenever the layout of a packet changes.
+const FORMAT_VERSION: u8 = 1;
+const PRESET_DUMP: u8 = 0x01;
+const SESSION_DUMP: u8 = 0x02;
+/// bytes of TOML per packet, small enough that neither ALSA nor a DIN cable chokes on one.
+const PACKET_SIZE: usize = 256;
+/// start, manufacturer, tag, version, kind, two bytes each of packet index and count.
+const HEADER_LEN: usize = 10;
+/// how long a byte takes at MIDI's 31250 baud, sending waits this long per byte between packets.
+pub const BYTE_TIME: Duration = Duration::from_micros(320);
+
+/// what can be sent between two synths as SysEx.
+///
+/// a dump is TOML, cut into packets of `F0 7D 'S' 'S' version kind index count data checksum F7`.
+/// index and count are 14 bit, LSB first. the data is 7 bit packed: each group of up to seven
+/// bytes is preceded by a byte holding their top bits, the first byte's in bit 0. the checksum
+/// makes the data bytes plus itself add up to a multiple of 128.
+#[derive(Debug, Clone)]
+pub enum Dump {
+    /// the sound of the synth, every engine and effect.
+    Preset(Box<Preset>),
+    /// the preset plus the sequencer.
+    Session(Box<Session>),
+}
+
+impl Dump {
+    pub fn preset(&self) -> &Preset {
+        match self {
+            Self::Preset(preset) => preset,
+            Self::Session(session) => &session.preset,
+        }
+    }
+
+    /// the SysEx messages to send for this dump, in order.
+    pub fn to_sysex(&self) -> Result<Vec<Vec<u8>>> {
+        let (kind, toml) = match self {
+            Self::Preset(preset) => (PRESET_DUMP, toml::to_string(preset)?),
+            Self::Session(session) => (SESSION_DUMP, toml::to_string(session)?),
+        };
+        let chunks: Vec<&[u8]> = toml.as_bytes().chunks(PACKET_SIZE).collect();
+        let count = chunks.len();
+
+        ensure!(count < 1 << 14, "the dump has too many packets to number");
+
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let data = pack(chunk);
+                let mut message = Vec::with_capacity(HEADER_LEN + data.len() + 2);
+                message.extend([SYSEX_START, MANUFACTURER_ID]);
+                message.extend(DEVICE_TAG);
+                message.extend([FORMAT_VERSION, kind]);
+                message.extend(u14(index));
+                message.extend(u14(count));
+                message.extend_from_slice(&data);
+                message.push(checksum(&data));
+                message.push(SYSEX_END);
+
+                message
+            })
+            .collect())
+    }
+
+    fn parse(kind: u8, toml: &[u8]) -> Result<Self> {
+        let toml = std::str::from_utf8(toml)?;
+
+        Ok(match kind {
+            PRESET_DUMP => Self::Preset(Box::new(toml::from_str(toml)?)),
+            SESSION_DUMP => Self::Session(Box::new(toml::from_str(toml)?)),
+            _ => bail!("unknown dump kind {kind:#04x}"),
+        })
+    }
+}
+
+fn u14(n: usize) -> [u8; 2] {
+    [(n & 0x7f) as u8, ((n >> 7) & 0x7f) as u8]
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    let sum = data.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+
+    0_u8.wrapping_sub(sum) & 0x7f
+}
+
+/// packs 8 bit bytes into the 7 bit bytes SysEx can carry.
+fn pack(bytes: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(bytes.len() + bytes.len().div_ceil(7));
+
+    for group in bytes.chunks(7) {
+        let top_bits = group
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, byte)| bits | (byte >> 7) << i);
+        packed.push(top_bits);
+        packed.extend(group.iter().map(|byte| byte & 0x7f));
+    }
+
+    packed
+}
+
+fn unpack(packed: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(packed.len());
+
+    for group in packed.chunks(8) {
+        let (top_bits, group) = (group[0], &group[1..]);
+        bytes.extend(
+            group
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte | ((top_bits >> i) & 1) << 7),
+        );
+    }
+
+    bytes
+}
+
+/// puts the packets of a dump back together. each MIDI input needs its own so dumps coming in on
+/// two ports at once don't mix.
+#[derive(Debug, Clone, Default)]
+pub struct SysExReceiver {
+    /// the kind and packet count of the dump coming in.
+    dump: Option<(u8, usize)>,
+    /// the TOML received so far.
+    toml: Vec<u8>,
+    /// the index of the packet expected next.
+    next: usize,
+}
+
+impl SysExReceiver {
+    /// takes in one SysEx message. returns the dump once its last packet arrives. SysEx that isn't
+    /// one of our dumps is ignored.
+    pub fn receive(&mut self, message: &[u8]) -> Option<Dump> {
+        let Some((header, rest)) = message.split_first_chunk::<HEADER_LEN>() else {
+            return None;
+        };
+        let [start, id, tag @ .., version, kind, index_lsb, index_msb, count_lsb, count_msb] =
+            *header;
+
+        if start != SYSEX_START || id != MANUFACTURER_ID || tag != DEVICE_TAG {
+            return None;
+        }
+
+        if version != FORMAT_VERSION {
+            warn!("ignoring a SysEx dump in format {version}, this synth reads {FORMAT_VERSION}");
+            return None;
+        }
+
+        let index = index_lsb as usize | (index_msb as usize) << 7;
+        let count = count_lsb as usize | (count_msb as usize) << 7;
+
+        let Some((data, [sum, SYSEX_END])) = rest.split_last_chunk() else {
+            warn!("dropping a SysEx dump, packet {index} is cut short");
+            self.reset();
+            return None;
+        };
+
+        if checksum(data) != *sum {
+            warn!("dropping a SysEx dump, packet {index} failed its checksum");
+            self.reset();
+            return None;
+        }
+
+        // a first packet always starts over, a dump that stopped half way is lost.
+        if index == 0 {
+            self.reset();
+            self.dump = Some((kind, count));
+        } else if self.dump != Some((kind, count)) || index != self.next {
+            warn!("dropping a SysEx dump, packet {index} of {count} came out of order");
+            self.reset();
+            return None;
+        }
+
+        self.toml.extend(unpack(data));
+        self.next = index + 1;
+
+        if self.next < count {
+            return None;
+        }
+
+        let dump = Dump::parse(kind, &self.toml);
+        self.reset();
+
+        match dump {
+            Ok(dump) => {
+                info!("received a SysEx dump in {count} packets");
+                Some(dump)
+            }
+            Err(e) => {
+                warn!("couldn't read a SysEx dump: {e}");
+                None
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        effects::EffectType,
+        pygame_coms::{Knob, SynthEngineType},
+        session::{EffectPatch, EnginePatch},
+        synth_engines::{
+            wave_table::{
+                ADSRPatch, AftertouchMod, LowPassPatch, ModPatch, OscPatch, WaveTablePatch,
+            },
+            BendRange,
+        },
+    };
+    use wavetable_synth::common::{LowPass, LowPassParam, ModMatrixDest, ModMatrixSrc};
+
+    /// a preset long enough to take a few packets.
+    fn preset() -> Preset {
+        let engines = [SynthEngineType::SubSynth, SynthEngineType::B3Organ]
+            .into_iter()
+            .map(|engine| EnginePatch {
+                engine,
+                pan: -0.25,
+                level: 0.8,
+                mute: false,
+                knobs: [Knob::One, Knob::Two, Knob::Three, Knob::Four]
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, knob)| (knob, i as f32 / 3.0))
+                    .collect(),
+                gui: Default::default(),
+                velocity: None,
+                bend: BendRange::default(),
+            })
+            .collect();
+
+        Preset {
+            engine: SynthEngineType::B3Organ,
+            effect: EffectType::Chorus,
+            effect_on: true,
+            engines,
+            effects: vec![EffectPatch {
+                effect: EffectType::Chorus,
+                params: [("speed".to_string(), 0.5)].into_iter().collect(),
+            }],
+            wave_table: Some(WaveTablePatch {
+                oscs: vec![
+                    OscPatch {
+                        on: true,
+                        level: 0.8,
+                        detune: 0.1,
+                        offset: -12,
+                    };
+                    3
+                ],
+                filters: vec![
+                    LowPassPatch {
+                        cutoff: 1_200.0,
+                        res: 0.3,
+                        mix: 1.0,
+                        key_track: true,
+                    };
+                    2
+                ],
+                envs: vec![
+                    ADSRPatch {
+                        atk: 0.01,
+                        dcy: 0.2,
+                        sus: 0.7,
+                        rel: 0.5,
+                    };
+                    2
+                ],
+                lfos: vec![2.0; 4],
+                mod_matrix: vec![ModPatch {
+                    src: ModMatrixSrc::Lfo(1),
+                    dest: ModMatrixDest::LowPass {
+                        low_pass: LowPass::LP1,
+                        param: LowPassParam::Cutoff,
+                    },
+                    amt: 0.4,
+                    bipolar: true,
+                }],
+                aftertouch_mods: vec![AftertouchMod {
+                    dest: ModMatrixDest::LowPass {
+                        low_pass: LowPass::LP2,
+                        param: LowPassParam::Res,
+                    },
+                    amt: 0.2,
+                    bipolar: false,
+                }],
+            }),
+        }
+    }
+
+    fn packets() -> Vec<Vec<u8>> {
+        let packets = Dump::Preset(Box::new(preset())).to_sysex().unwrap();
+        assert!(packets.len() > 2, "the test preset fits in too few packets");
+
+        packets
+    }
+
+    fn receive_all(receiver: &mut SysExReceiver, packets: &[Vec<u8>]) -> Option<Dump> {
+        packets
+            .iter()
+            .fold(None, |_, packet| receiver.receive(packet))
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let packed = pack(&bytes);
+
+        assert!(packed.iter().all(|byte| *byte < 0x80));
+        assert_eq!(unpack(&packed), bytes);
+    }
+
+    #[test]
+    fn preset_round_trip() {
+        let packets = packets();
+
+        for packet in packets.iter() {
+            assert_eq!(packet.first(), Some(&SYSEX_START));
+            assert_eq!(packet.last(), Some(&SYSEX_END));
+            assert!(packet[1..packet.len() - 1].iter().all(|byte| *byte < 0x80));
+        }
+
+        let mut receiver = SysExReceiver::default();
+
+        for packet in &packets[..packets.len() - 1] {
+            assert!(receiver.receive(packet).is_none());
+        }
+
+        match receiver.receive(packets.last().unwrap()) {
+            Some(Dump::Preset(received)) => assert_eq!(*received, preset()),
+            dump => panic!("expected the preset back, got {dump:?}"),
+        }
+    }
+
+    #[test]
+    fn out_of_order_is_dropped() {
+        let packets = packets();
+        let mut receiver = SysExReceiver::default();
+        let mut swapped = packets.clone();
+        swapped.swap(1, 2);
+
+        assert!(receive_all(&mut receiver, &swapped).is_none());
+        assert!(receive_all(&mut receiver, &packets[1..]).is_none());
+        assert!(receive_all(&mut receiver, &packets).is_some());
+    }
+
+    #[test]
+    fn bad_checksum_is_dropped() {
+        let packets = packets();
+        let mut receiver = SysExReceiver::default();
+        let mut corrupt = packets.clone();
+        corrupt[1][HEADER_LEN] ^= 0x01;
+
+        assert!(receive_all(&mut receiver, &corrupt).is_none());
+        assert!(receive_all(&mut receiver, &packets).is_some());
+    }
+}